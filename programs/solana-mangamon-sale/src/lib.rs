@@ -1,4 +1,16 @@
+#![allow(clippy::result_large_err)]
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::slot_hashes::MAX_ENTRIES;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+pub mod price;
+pub mod sale_state;
+pub mod vesting;
+
+use price::Rounding;
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
@@ -6,19 +18,27 @@ declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 pub mod solana_mangamon_sale {
     use super::*;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize(
         ctx: Context<Initialize>,
-        _ido_token_price_ratio: u64,
+        _ido_token_price: Price,
         _start_date_funding: i64,
         _end_date_funding: i64,
         _end_date_of_claiming_tokens: i64,
         _claiming_initial_percentage: u8,
         _in_one_transaction: bool,
+        _hard_cap_ido_tokens: u128,
+        _is_fund_first: bool,
     ) -> Result<()> {
-        assert!(
+        require!(
             _start_date_funding < _end_date_funding,
-            "The starting date of the funding should be before the end date of the funding"
+            SaleError::InvalidFundingDates
         );
+        require!(
+            _end_date_funding < _end_date_of_claiming_tokens,
+            SaleError::FundingOverlapsClaiming
+        );
+        require!(_hard_cap_ido_tokens > 0, SaleError::AmountNotPositive);
         // Get a mutable reference to the accounts
         let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
         let sale_account = &mut ctx.accounts.sale_account;
@@ -28,13 +48,31 @@ pub mod solana_mangamon_sale {
         // Initialize fields of authorized_sale_account
         // Set admin
         authorized_sale_account.admin = *user.key;
+        // Set the sale account holding the accounting of this sale
+        authorized_sale_account.sale_account = sale_account.key();
+        authorized_sale_account.sale_account_bump = *ctx.bumps.get("sale_account").unwrap();
+        // Set the admin as allocation authority, until delegated
+        authorized_sale_account.allocation_authority = *user.key;
+
+        // Set the token buyers pay with, and the vault holding their payments
+        authorized_sale_account.pay_token_mint = ctx.accounts.pay_token_mint.key();
+        authorized_sale_account.pay_token_decimals = ctx.accounts.pay_token_mint.decimals;
+        authorized_sale_account.pay_token_vault_bump = *ctx.bumps.get("pay_token_vault").unwrap();
+        // Set the token being sold, and the vault holding it until claimed
+        authorized_sale_account.ido_token_mint = ctx.accounts.ido_token_mint.key();
+        authorized_sale_account.ido_token_decimals = ctx.accounts.ido_token_mint.decimals;
+        authorized_sale_account.ido_token_vault_bump = *ctx.bumps.get("ido_token_vault").unwrap();
 
         // Set initial sale token price
-        authorized_sale_account.ido_token_price_ratio = _ido_token_price_ratio;
-        authorized_sale_account.ido_token_price_multiplier = 10000;
+        _ido_token_price.validate()?;
+        authorized_sale_account.ido_token_price = _ido_token_price;
 
         // Set amount of tokens to be sold
         authorized_sale_account.tokens_for_sale = 0;
+        // Set most tokens that can be sold
+        authorized_sale_account.hard_cap_ido_tokens = _hard_cap_ido_tokens;
+        // No soft cap, until set by the admin
+        authorized_sale_account.soft_cap_pay_tokens = 0;
 
         // Set starting and ending dates of funding
         authorized_sale_account.start_date_funding = _start_date_funding;
@@ -42,6 +80,10 @@ pub mod solana_mangamon_sale {
 
         // Tokens to be claimed until
         authorized_sale_account.end_date_of_claiming_tokens = _end_date_of_claiming_tokens;
+        // No cliff, until set by the admin
+        authorized_sale_account.cliff_date_of_claiming_tokens = 0;
+        // Linear vesting, until an unlock table is set by the admin
+        authorized_sale_account.tranche_schedule_bump = *ctx.bumps.get("tranche_schedule").unwrap();
 
         // Default value, until funds have been made
         authorized_sale_account.is_ido_token_funded_to_contract = false;
@@ -49,49 +91,180 @@ pub mod solana_mangamon_sale {
         authorized_sale_account.is_funding_canceled = false;
         // Set IDO tokens need to be payed in one transaction
         authorized_sale_account.in_one_transaction = _in_one_transaction;
+        // Set if the IDO tokens have to be funded before they can be bought
+        authorized_sale_account.is_fund_first = _is_fund_first;
         // Set claiming
         authorized_sale_account.is_claiming_open = false;
+        authorized_sale_account.has_claiming_opened = false;
 
         // Initialize fields of sale_account
         // Set count of total pay tokens spend
         sale_account.total_spend_pay_tokens = 0;
+        // Set count of total pay tokens withdrawn by the admin
+        sale_account.total_withdrawn_pay_tokens = 0;
         // Set count of total IDO tokens sold
         sale_account.total_allocated_ido_tokens = 0;
+        // Set count of total unsold IDO tokens withdrawn by the admin
+        sale_account.total_withdrawn_unsold_ido_tokens = 0;
         // Set investor count
         sale_account.investor_count = 0;
+        // Set count of buyers added to the buyer registry
+        sale_account.buyer_registry_count = 0;
+        // Set count of lottery tickets registered
+        sale_account.lottery_ticket_count = 0;
         Ok(())
     }
 
-    /// Builds a PDA hashmap, and initializes its fields
-    pub fn creat_buyer_info(
-        ctx: Context<CreatBuyerInfo>,
-        _spend_pay_tokens: u128,
-        _ido_tokens_to_get: u128,
-    ) -> Result<()> {
+    /// Builds a PDA hashmap, and initializes its fields. Only purchases raise the amounts
+    pub fn creat_buyer_info(ctx: Context<CreatBuyerInfo>) -> Result<()> {
         let buyer_info = &mut ctx.accounts.buyer_info;
-        buyer_info.spend_pay_tokens = _spend_pay_tokens;
-        buyer_info.ido_tokens_to_get = _ido_tokens_to_get;
+        buyer_info.spend_pay_tokens = 0;
+        buyer_info.ido_tokens_to_get = 0;
         buyer_info.ido_tokens_claimed = 0;
         buyer_info.has_claimed_pay_tokens = false;
+        buyer_info.is_in_buyer_registry = false;
+        buyer_info.vesting_override = None;
         buyer_info.bump = *ctx.bumps.get("buyer_info").unwrap();
         Ok(())
     }
 
+    /// Appends a buyer to the current page of the buyer registry, creating the page when
+    /// the previous one is full. Anyone can crank this for any buyer
+    pub fn add_to_buyer_registry(
+        ctx: Context<BuyerRegistryUpdate>,
+        _buyer: Pubkey,
+        _page_index: u64,
+    ) -> Result<()> {
+        let sale_account = &mut ctx.accounts.sale_account;
+        require!(
+            _page_index == sale_account.buyer_registry_count / BUYER_REGISTRY_PAGE_SIZE as u64,
+            SaleError::WrongBuyerRegistryPage
+        );
+        let buyer_info = &mut ctx.accounts.buyer_info;
+        require!(buyer_info.ido_tokens_to_get > 0, SaleError::NotABuyer);
+        require!(
+            !buyer_info.is_in_buyer_registry,
+            SaleError::AlreadyInBuyerRegistry
+        );
+        buyer_info.is_in_buyer_registry = true;
+        let buyer_registry_page = &mut ctx.accounts.buyer_registry_page;
+        buyer_registry_page.buyers.push(_buyer);
+        sale_account.buyer_registry_count = sale_account
+            .buyer_registry_count
+            .checked_add(1)
+            .ok_or(SaleError::MathOverflow)?;
+        Ok(())
+    }
+
     // Setters
+    /// Change the price of the IDO token, only before the funding starts. Selling the whole
+    /// hard cap should still reach the soft cap
+    pub fn set_ido_token_price(
+        ctx: Context<AdminOnlyUpdate>,
+        _ido_token_price: Price,
+    ) -> Result<()> {
+        ctx.accounts.is_pre_start_funding()?;
+        _ido_token_price.validate()?;
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        let _old_ido_token_price = authorized_sale_account.ido_token_price;
+        authorized_sale_account.ido_token_price = _ido_token_price;
+        require!(
+            authorized_sale_account.soft_cap_pay_tokens
+                <= authorized_sale_account
+                    .pay_tokens_for(authorized_sale_account.hard_cap_ido_tokens, Rounding::Up)?,
+            SaleError::SoftCapAboveHardCap
+        );
+        emit!(ChangedIdoTokenPrice {
+            admin: *ctx.accounts.admin.key,
+            old_ido_token_price: _old_ido_token_price,
+            ido_token_price: authorized_sale_account.ido_token_price
+        });
+        Ok(())
+    }
+    /// Change the funding dates. Before the funding starts both can be moved, once it is
+    /// open the end date can only be extended
+    pub fn set_funding_dates(
+        ctx: Context<AdminOnlyUpdate>,
+        _start_date_funding: i64,
+        _end_date_funding: i64,
+    ) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        let _old_start_date_funding = authorized_sale_account.start_date_funding;
+        let _old_end_date_funding = authorized_sale_account.end_date_funding;
+        if now_ts >= _old_start_date_funding {
+            require!(
+                now_ts <= _old_end_date_funding
+                    && _start_date_funding == _old_start_date_funding
+                    && _end_date_funding >= _old_end_date_funding,
+                SaleError::FundingDatesLocked
+            );
+        } else {
+            require!(
+                now_ts <= _start_date_funding,
+                SaleError::FundingAlreadyStarted
+            );
+        }
+        require!(
+            _start_date_funding < _end_date_funding,
+            SaleError::InvalidFundingDates
+        );
+        // The claiming starts once enabled, but should always end after the funding
+        require!(
+            _end_date_funding < authorized_sale_account.end_date_of_claiming_tokens
+                && (authorized_sale_account.start_date_of_claiming_tokens == 0
+                    || _end_date_funding <= authorized_sale_account.start_date_of_claiming_tokens),
+            SaleError::FundingOverlapsClaiming
+        );
+        require!(
+            authorized_sale_account.end_date_lottery_registration <= _start_date_funding,
+            SaleError::InvalidLotteryDates
+        );
+        authorized_sale_account.start_date_funding = _start_date_funding;
+        authorized_sale_account.end_date_funding = _end_date_funding;
+        emit!(ChangedFundingDates {
+            admin: *ctx.accounts.admin.key,
+            old_start_date_funding: _old_start_date_funding,
+            old_end_date_funding: _old_end_date_funding,
+            start_date_funding: authorized_sale_account.start_date_funding,
+            end_date_funding: authorized_sale_account.end_date_funding
+        });
+        Ok(())
+    }
+    /// Set the least pay tokens the funding should raise, only before the funding starts.
+    /// Falling short, buyers get refunded instead of claiming. If 0, then there is no soft cap
+    pub fn set_soft_cap_pay_tokens(
+        ctx: Context<AdminOnlyUpdate>,
+        _soft_cap_pay_tokens: u128,
+    ) -> Result<()> {
+        ctx.accounts.is_pre_start_funding()?;
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        require!(
+            _soft_cap_pay_tokens
+                <= authorized_sale_account
+                    .pay_tokens_for(authorized_sale_account.hard_cap_ido_tokens, Rounding::Up)?,
+            SaleError::SoftCapAboveHardCap
+        );
+        let _old_soft_cap_pay_tokens = authorized_sale_account.soft_cap_pay_tokens;
+        authorized_sale_account.soft_cap_pay_tokens = _soft_cap_pay_tokens;
+        emit!(ChangedSoftCapPayTokens {
+            admin: *ctx.accounts.admin.key,
+            old_soft_cap_pay_tokens: _old_soft_cap_pay_tokens,
+            soft_cap_pay_tokens: authorized_sale_account.soft_cap_pay_tokens
+        });
+        Ok(())
+    }
     /// Change the initial percentage of token allocation to be claimed
     pub fn set_initial_percentage_allocation_ido_tokens(
         ctx: Context<AdminOnlyUpdate>,
         _percentage: u8,
     ) -> Result<()> {
         let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
-        assert_eq!(
-            authorized_sale_account.is_claiming_open, false,
-            "Claiming is already enabled"
-        );
-        assert!(
-            _percentage <= 100,
-            "You cannot give more than 100 percent of the token allocation"
+        require!(
+            !authorized_sale_account.is_claiming_open,
+            SaleError::ClaimingAlreadyEnabled
         );
+        require!(_percentage <= 100, SaleError::PercentageAboveHundred);
         let _old_initial_percentage_allocation_ido_tokens =
             authorized_sale_account.initial_percentage_allocation_ido_tokens;
         authorized_sale_account.initial_percentage_allocation_ido_tokens = _percentage;
@@ -112,10 +285,20 @@ pub mod solana_mangamon_sale {
         _start_date_of_claiming_tokens: i64,
     ) -> Result<()> {
         let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        require!(
+            authorized_sale_account.end_date_funding <= _start_date_of_claiming_tokens,
+            SaleError::FundingOverlapsClaiming
+        );
+        require!(
+            _start_date_of_claiming_tokens < authorized_sale_account.end_date_of_claiming_tokens,
+            SaleError::InvalidClaimingDates
+        );
         let _old_is_claiming_open = authorized_sale_account.is_claiming_open;
 
         authorized_sale_account.is_claiming_open = _is_claiming_open;
         authorized_sale_account.start_date_of_claiming_tokens = _start_date_of_claiming_tokens;
+        // Buyers may have claimed already, even once claiming is closed again
+        authorized_sale_account.has_claiming_opened |= _is_claiming_open;
 
         emit!(ChangedIsClaimingOpen {
             admin: *ctx.accounts.admin.key,
@@ -130,9 +313,17 @@ pub mod solana_mangamon_sale {
         _end_date_of_claiming_tokens: i64,
     ) -> Result<()> {
         let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
-        assert_eq!(
-            authorized_sale_account.is_claiming_open, false,
-            "Claiming is already enabled"
+        require!(
+            !authorized_sale_account.is_claiming_open,
+            SaleError::ClaimingAlreadyEnabled
+        );
+        require!(
+            authorized_sale_account.end_date_funding < _end_date_of_claiming_tokens,
+            SaleError::FundingOverlapsClaiming
+        );
+        require!(
+            authorized_sale_account.start_date_of_claiming_tokens < _end_date_of_claiming_tokens,
+            SaleError::InvalidClaimingDates
         );
         let _old_end_date_of_claiming_tokens = authorized_sale_account.end_date_of_claiming_tokens;
         authorized_sale_account.end_date_of_claiming_tokens = _end_date_of_claiming_tokens;
@@ -143,21 +334,347 @@ pub mod solana_mangamon_sale {
         });
         Ok(())
     }
+    /// Set the cliff date, until when only the initial allocation can be claimed
+    pub fn set_cliff_date_of_claiming_tokens(
+        ctx: Context<AdminOnlyUpdate>,
+        _cliff_date_of_claiming_tokens: i64,
+    ) -> Result<()> {
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        require!(
+            !authorized_sale_account.is_claiming_open,
+            SaleError::ClaimingAlreadyEnabled
+        );
+        require!(
+            _cliff_date_of_claiming_tokens <= authorized_sale_account.end_date_of_claiming_tokens,
+            SaleError::InvalidCliffDate
+        );
+        let _old_cliff_date_of_claiming_tokens =
+            authorized_sale_account.cliff_date_of_claiming_tokens;
+        authorized_sale_account.cliff_date_of_claiming_tokens = _cliff_date_of_claiming_tokens;
+        emit!(ChangedCliffDateOfClaimingTokens {
+            admin: *ctx.accounts.admin.key,
+            old_cliff_date_of_claiming_tokens: _old_cliff_date_of_claiming_tokens,
+            cliff_date_of_claiming_tokens: authorized_sale_account.cliff_date_of_claiming_tokens
+        });
+        Ok(())
+    }
+    /// Set the unlock table of (timestamp, cumulative bps) points, releasing the IDO tokens
+    /// in tranches instead of linearly. An empty table goes back to the linear vesting
+    pub fn set_tranche_schedule(
+        ctx: Context<AdminTrancheScheduleUpdate>,
+        _tranches: Vec<Tranche>,
+    ) -> Result<()> {
+        require!(
+            !ctx.accounts.authorized_sale_account.is_claiming_open,
+            SaleError::ClaimingAlreadyEnabled
+        );
+        require!(
+            _tranches.len() <= MAX_TRANCHES,
+            SaleError::InvalidTrancheSchedule
+        );
+        vesting::validate_tranches(&_tranches)?;
+        let tranche_schedule = &mut ctx.accounts.tranche_schedule;
+        tranche_schedule.tranches = _tranches;
+        emit!(ChangedTrancheSchedule {
+            admin: *ctx.accounts.admin.key,
+            tranches: tranche_schedule.tranches.clone()
+        });
+        Ok(())
+    }
+    /// Give a buyer his own linear vesting (initial percentage, cliff, duration), counted
+    /// from the start date of claiming, instead of the one of the sale. None removes it
+    pub fn set_buyer_vesting_override(
+        ctx: Context<AdminBuyerInfoUpdate>,
+        _buyer: Pubkey,
+        _vesting_override: Option<VestingOverride>,
+    ) -> Result<()> {
+        require!(
+            !ctx.accounts.authorized_sale_account.is_claiming_open,
+            SaleError::ClaimingAlreadyEnabled
+        );
+        if let Some(vesting_override) = _vesting_override {
+            require!(
+                vesting_override.initial_percentage <= 100,
+                SaleError::PercentageAboveHundred
+            );
+            require!(
+                vesting_override.cliff_duration >= 0
+                    && vesting_override.cliff_duration <= vesting_override.duration,
+                SaleError::InvalidVestingOverride
+            );
+        }
+        let buyer_info = &mut ctx.accounts.buyer_info;
+        let _old_vesting_override = buyer_info.vesting_override;
+        buyer_info.vesting_override = _vesting_override;
+        emit!(ChangedBuyerVestingOverride {
+            admin: *ctx.accounts.admin.key,
+            buyer: _buyer,
+            old_vesting_override: _old_vesting_override,
+            vesting_override: buyer_info.vesting_override
+        });
+        Ok(())
+    }
+
+    /// Set who, besides the admin, can write the buyers' allocations
+    pub fn set_allocation_authority(
+        ctx: Context<AdminOnlyUpdate>,
+        _allocation_authority: Pubkey,
+    ) -> Result<()> {
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        let _old_allocation_authority = authorized_sale_account.allocation_authority;
+        authorized_sale_account.allocation_authority = _allocation_authority;
+        emit!(ChangedAllocationAuthority {
+            admin: *ctx.accounts.admin.key,
+            old_allocation_authority: _old_allocation_authority,
+            allocation_authority: authorized_sale_account.allocation_authority
+        });
+        Ok(())
+    }
+    /// Set the merkle root of the whitelist of (buyer, max allocation) pairs
+    pub fn set_allocation_merkle_root(
+        ctx: Context<AdminOnlyUpdate>,
+        _allocation_merkle_root: [u8; 32],
+    ) -> Result<()> {
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        let _old_allocation_merkle_root = authorized_sale_account.allocation_merkle_root;
+        authorized_sale_account.allocation_merkle_root = _allocation_merkle_root;
+        emit!(ChangedAllocationMerkleRoot {
+            admin: *ctx.accounts.admin.key,
+            old_allocation_merkle_root: _old_allocation_merkle_root,
+            allocation_merkle_root: authorized_sale_account.allocation_merkle_root
+        });
+        Ok(())
+    }
+    /// Set the amount of IDO tokens a buyer is allowed to buy
+    pub fn set_allocation(
+        ctx: Context<AllocationAuthorityUpdate>,
+        _buyer: Pubkey,
+        _ido_tokens_allocation: u128,
+    ) -> Result<()> {
+        let allocation = &mut ctx.accounts.allocation;
+        let _old_ido_tokens_allocation = allocation.ido_tokens_allocation;
+        allocation.ido_tokens_allocation = _ido_tokens_allocation;
+        allocation.bump = *ctx.bumps.get("allocation").unwrap();
+        emit!(ChangedAllocation {
+            authority: *ctx.accounts.authority.key,
+            buyer: _buyer,
+            old_ido_tokens_allocation: _old_ido_tokens_allocation,
+            ido_tokens_allocation: allocation.ido_tokens_allocation
+        });
+        Ok(())
+    }
+
+    // Lottery
+    /// Set the lottery registration window, the number of winners, commit to the hash of
+    /// the secret that will be revealed, and name the future slot whose hash seeds the draw
+    pub fn setup_lottery(
+        ctx: Context<AdminOnlyUpdate>,
+        _start_date_lottery_registration: i64,
+        _end_date_lottery_registration: i64,
+        _number_of_lottery_winners: u64,
+        _lottery_commitment: [u8; 32],
+        _lottery_draw_slot: u64,
+    ) -> Result<()> {
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        require!(
+            !authorized_sale_account.is_lottery_played,
+            SaleError::LotteryAlreadyPlayed
+        );
+        require!(
+            ctx.accounts.sale_account.lottery_ticket_count == 0,
+            SaleError::LotteryAlreadyStarted
+        );
+        require!(
+            _start_date_lottery_registration < _end_date_lottery_registration
+                && _end_date_lottery_registration <= authorized_sale_account.start_date_funding,
+            SaleError::InvalidLotteryDates
+        );
+        require!(_number_of_lottery_winners > 0, SaleError::AmountNotPositive);
+        require!(
+            _lottery_draw_slot > Clock::get()?.slot,
+            SaleError::InvalidLotteryDrawSlot
+        );
+        authorized_sale_account.start_date_lottery_registration = _start_date_lottery_registration;
+        authorized_sale_account.end_date_lottery_registration = _end_date_lottery_registration;
+        authorized_sale_account.number_of_lottery_winners = _number_of_lottery_winners;
+        authorized_sale_account.lottery_commitment = _lottery_commitment;
+        authorized_sale_account.lottery_draw_slot = _lottery_draw_slot;
+        authorized_sale_account.lottery_secret = [0; 32];
+        authorized_sale_account.is_lottery_secret_revealed = false;
+        emit!(SetUpLottery {
+            admin: *ctx.accounts.admin.key,
+            start_date_lottery_registration: _start_date_lottery_registration,
+            end_date_lottery_registration: _end_date_lottery_registration,
+            number_of_lottery_winners: _number_of_lottery_winners,
+            lottery_commitment: _lottery_commitment,
+            lottery_draw_slot: _lottery_draw_slot
+        });
+        Ok(())
+    }
+    /// Register the user for the lottery, handing out the next ticket number
+    pub fn register_for_lottery(ctx: Context<LotteryRegistration>) -> Result<()> {
+        ctx.accounts.is_lottery_registration_open()?;
+        let sale_account = &mut ctx.accounts.sale_account;
+        let lottery_ticket = &mut ctx.accounts.lottery_ticket;
+        lottery_ticket.ticket_number = sale_account.lottery_ticket_count;
+        lottery_ticket.is_winner = false;
+        lottery_ticket.bump = *ctx.bumps.get("lottery_ticket").unwrap();
+        sale_account.lottery_ticket_count = sale_account
+            .lottery_ticket_count
+            .checked_add(1)
+            .ok_or(SaleError::MathOverflow)?;
+        emit!(RegisteredForLottery {
+            buyer: *ctx.accounts.user.key,
+            ticket_number: lottery_ticket.ticket_number
+        });
+        Ok(())
+    }
+    /// Reveal the committed secret, once the tickets are final and before the draw slot.
+    /// The hash of the draw slot does not exist yet, so the admin cannot tell which
+    /// outcome revealing, or not revealing, leads to
+    pub fn reveal_lottery_secret(
+        ctx: Context<AdminLotteryReveal>,
+        _lottery_secret: [u8; 32],
+    ) -> Result<()> {
+        ctx.accounts.is_lottery_registration_closed()?;
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        require!(
+            Clock::get()?.slot < authorized_sale_account.lottery_draw_slot,
+            SaleError::LotteryRevealClosed
+        );
+        require!(
+            hashv(&[&_lottery_secret]).to_bytes() == authorized_sale_account.lottery_commitment,
+            SaleError::InvalidLotteryReveal
+        );
+        authorized_sale_account.lottery_secret = _lottery_secret;
+        authorized_sale_account.is_lottery_secret_revealed = true;
+        emit!(RevealedLotterySecret {
+            admin: *ctx.accounts.admin.key,
+            lottery_secret: _lottery_secret
+        });
+        Ok(())
+    }
+    /// Draw the lottery, once the draw slot has passed. The seed mixes the revealed secret
+    /// (zeros if the admin missed the reveal) with the final ticket count and the hash of
+    /// the draw slot named on setup, so neither the admin nor the registrants can pick the
+    /// outcome. Anyone can crank this
+    pub fn draw_lottery(ctx: Context<LotteryDraw>) -> Result<()> {
+        ctx.accounts.is_lottery_registration_closed()?;
+        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
+        require!(
+            !authorized_sale_account.is_lottery_played,
+            SaleError::LotteryAlreadyPlayed
+        );
+        require!(
+            Clock::get()?.slot > authorized_sale_account.lottery_draw_slot,
+            SaleError::LotteryDrawSlotNotReached
+        );
+        let lottery_ticket_count = ctx.accounts.sale_account.lottery_ticket_count;
+        let recent_slot_hashes = ctx.accounts.recent_slot_hashes.try_borrow_data()?;
+        let lottery_draw_slot_hash = find_slot_hash(
+            &recent_slot_hashes,
+            authorized_sale_account.lottery_draw_slot,
+        )
+        .ok_or(SaleError::LotteryDrawSlotExpired)?;
+        let _lottery_seed = hashv(&[
+            &authorized_sale_account.lottery_secret,
+            authorized_sale_account.key().as_ref(),
+            &lottery_ticket_count.to_le_bytes(),
+            lottery_draw_slot_hash,
+        ])
+        .to_bytes();
+        drop(recent_slot_hashes);
+
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        authorized_sale_account.lottery_seed = _lottery_seed;
+        authorized_sale_account.is_lottery_played = true;
+        emit!(DrewLottery {
+            lottery_seed: _lottery_seed,
+            lottery_ticket_count,
+            number_of_lottery_winners: authorized_sale_account.number_of_lottery_winners,
+            is_lottery_secret_revealed: authorized_sale_account.is_lottery_secret_revealed
+        });
+        Ok(())
+    }
+    /// Name a new draw slot, only once nobody drew the lottery while the hash of the
+    /// previous one was still available
+    pub fn set_lottery_draw_slot(
+        ctx: Context<AdminOnlyUpdate>,
+        _lottery_draw_slot: u64,
+    ) -> Result<()> {
+        let current_slot = Clock::get()?.slot;
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
+        require!(
+            !authorized_sale_account.is_lottery_played,
+            SaleError::LotteryAlreadyPlayed
+        );
+        require!(
+            current_slot
+                > authorized_sale_account
+                    .lottery_draw_slot
+                    .saturating_add(MAX_ENTRIES as u64),
+            SaleError::LotteryDrawSlotNotExpired
+        );
+        require!(
+            _lottery_draw_slot > current_slot,
+            SaleError::InvalidLotteryDrawSlot
+        );
+        let _old_lottery_draw_slot = authorized_sale_account.lottery_draw_slot;
+        authorized_sale_account.lottery_draw_slot = _lottery_draw_slot;
+        emit!(ChangedLotteryDrawSlot {
+            admin: *ctx.accounts.admin.key,
+            old_lottery_draw_slot: _old_lottery_draw_slot,
+            lottery_draw_slot: authorized_sale_account.lottery_draw_slot
+        });
+        Ok(())
+    }
+    /// Mark a drawn ticket as winner and grant its owner an equal share of the hard cap
+    /// as allocation. Anyone can crank this for any buyer
+    pub fn mark_lottery_winner(ctx: Context<LotteryWinnerUpdate>, _buyer: Pubkey) -> Result<()> {
+        ctx.accounts.is_lottery_played()?;
+        require!(
+            !ctx.accounts.lottery_ticket.is_winner,
+            SaleError::AlreadyMarkedWinner
+        );
+        require!(
+            ctx.accounts.is_winning_ticket()?,
+            SaleError::NotALotteryWinner
+        );
+        let _ido_tokens_allocation = ctx
+            .accounts
+            .authorized_sale_account
+            .hard_cap_ido_tokens
+            .checked_div(u128::from(ctx.accounts.winners_count()))
+            .ok_or(SaleError::MathOverflow)?;
+        let lottery_ticket = &mut ctx.accounts.lottery_ticket;
+        lottery_ticket.is_winner = true;
+        let allocation = &mut ctx.accounts.allocation;
+        allocation.ido_tokens_allocation = _ido_tokens_allocation;
+        allocation.bump = *ctx.bumps.get("allocation").unwrap();
+        emit!(MarkedLotteryWinner {
+            buyer: _buyer,
+            ticket_number: lottery_ticket.ticket_number,
+            ido_tokens_allocation: _ido_tokens_allocation
+        });
+        Ok(())
+    }
 
     // Getters
-    /// Returns a list of all buyers (wallet addresses)
-    pub fn get_buyers(ctx: Context<ReadAccounts>) -> Result<Vec<Pubkey>> {
-        Ok(ctx.accounts.sale_account.buyers_list.clone())
-    }
-    /// Returns boolean of the wallet address when he is a buyer or not
-    pub fn is_buyer(ctx: Context<ReadAccounts>, _buyer: Pubkey) -> Result<bool> {
-        let buyers_list = &ctx.accounts.sale_account.buyers_list;
-        for buyer in buyers_list {
-            if buyer.to_bytes() == _buyer.to_bytes() {
-                return Ok(true);
-            }
+    /// Returns a page of the buyer registry (wallet addresses)
+    pub fn get_buyers(
+        ctx: Context<ReadBuyerRegistryPage>,
+        _page_index: u64,
+    ) -> Result<Vec<Pubkey>> {
+        Ok(ctx.accounts.buyer_registry_page.buyers.clone())
+    }
+    /// Returns boolean of the wallet address when he is a buyer or not, read from his
+    /// buyer info, which only exists once created
+    pub fn is_buyer(ctx: Context<ReadBuyerInfoIfCreated>, _buyer: Pubkey) -> Result<bool> {
+        let buyer_info = &ctx.accounts.buyer_info;
+        if buyer_info.data_is_empty() {
+            return Ok(false);
         }
-        Ok(false)
+        Ok(Account::<BuyerInfo>::try_from(buyer_info)?.ido_tokens_to_get > 0)
     }
     /// Get total tokens bought by msg.sender, and total tokens spent
     pub fn get_total_ido_tokens_bought_and_pay_tokens_spend(
@@ -179,403 +696,208 @@ pub mod solana_mangamon_sale {
             sale_account.total_spend_pay_tokens,
         ])
     }
-    /// Returns the claimable tokens at this point in time
+    /// Returns the claimable tokens at this point in time, none until claiming is open
     pub fn get_claimable_tokens(
         ctx: Context<ReadBuyerInfoAndAccounts>,
         _buyer: Pubkey,
     ) -> Result<u128> {
-        let buyer_info = &ctx.accounts.buyer_info;
-        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
-        let initial_tokens_to_get = calculate_ido_tokens_bought(
-            Context::new(
-                &crate::id(),
-                &mut ReadAccounts {
-                    authorized_sale_account: ctx.accounts.authorized_sale_account.clone(),
-                    sale_account: ctx.accounts.sale_account.clone(),
-                    user: ctx.accounts.user.clone(),
-                },
-                &[],
-                std::collections::BTreeMap::new(),
-            ),
-            ctx.accounts.buyer_info.spend_pay_tokens,
-        )?;
-
-        let _seconds_in_total_between_start_and_end_date_claiming_tokens = authorized_sale_account
-            .end_date_of_claiming_tokens
-            .checked_sub(authorized_sale_account.start_date_of_claiming_tokens)
-            .unwrap();
-        let mut _ido_tokens_per_second = buyer_info
-            .ido_tokens_to_get
-            .checked_div(_seconds_in_total_between_start_and_end_date_claiming_tokens as u128)
-            .unwrap();
-        let mut _total_tokens_to_get = 0u128;
-
-        // Allocate all the tokens, if current time already surpassed the endDateOfClaimingTokens
-        if Clock::get().unwrap().unix_timestamp
-            > authorized_sale_account.end_date_of_claiming_tokens
-        {
-            _total_tokens_to_get = buyer_info.ido_tokens_to_get;
-        } else if authorized_sale_account.initial_percentage_allocation_ido_tokens > 0 {
-            // Calculates the _totalTokensToGet with this percentage
-            let _initial_tokens_to_get = initial_tokens_to_get
-                .checked_div(100)
-                .unwrap()
-                .checked_mul(
-                    authorized_sale_account.initial_percentage_allocation_ido_tokens as u128,
-                )
-                .unwrap();
-            if Clock::get().unwrap().unix_timestamp
-                >= authorized_sale_account.start_date_of_claiming_tokens
-            {
-                // Removes the initial tokes to get from the total supply tokens to get percentage
-                _ido_tokens_per_second = buyer_info
-                    .ido_tokens_to_get
-                    .checked_sub(_initial_tokens_to_get)
-                    .unwrap()
-                    .checked_div(
-                        _seconds_in_total_between_start_and_end_date_claiming_tokens as u128,
-                    )
-                    .unwrap();
-                // Calculate how many tokens to get since startDateOfClaimingTokens
-                let _seconds_passed_since_start = Clock::get()
-                    .unwrap()
-                    .unix_timestamp
-                    .checked_sub(authorized_sale_account.start_date_of_claiming_tokens)
-                    .unwrap();
-                _total_tokens_to_get = _ido_tokens_per_second
-                    .checked_mul(_seconds_passed_since_start as u128)
-                    .unwrap();
-            }
-            // Add the initial tokens to get to the tokens that can be claimed
-            _total_tokens_to_get = _total_tokens_to_get
-                .checked_add(_initial_tokens_to_get)
-                .unwrap();
-        } else {
-            // End date has not yet been reached
-            let _seconds_passed_since_start = Clock::get()
-                .unwrap()
-                .unix_timestamp
-                .checked_sub(authorized_sale_account.start_date_of_claiming_tokens)
-                .unwrap();
-            _total_tokens_to_get = _ido_tokens_per_second
-                .checked_mul(_seconds_passed_since_start as u128)
-                .unwrap();
+        if !ctx.accounts.authorized_sale_account.is_claiming_open {
+            return Ok(0);
         }
-        // Subtract previous already claimed tokens
-        _total_tokens_to_get = _total_tokens_to_get
-            .checked_sub(buyer_info.ido_tokens_claimed)
-            .unwrap();
-        Ok(_total_tokens_to_get)
+        ctx.accounts
+            .authorized_sale_account
+            .vesting_schedule(&ctx.accounts.tranche_schedule, &ctx.accounts.buyer_info)
+            .releasable(Clock::get()?.unix_timestamp, &ctx.accounts.buyer_info)
     }
 
     // Checks
     /// Check if the Funding has started ans has not ended
     pub fn is_funding_open(ctx: Context<ReadAccounts>) -> Result<bool> {
-        let is_open = Clock::get().unwrap().unix_timestamp
+        let is_open = Clock::get()?.unix_timestamp
             >= ctx.accounts.authorized_sale_account.start_date_funding
-            && Clock::get().unwrap().unix_timestamp
+            && Clock::get()?.unix_timestamp
                 <= ctx.accounts.authorized_sale_account.end_date_funding;
         Ok(is_open)
     }
     /// Check if the current date is pre-funding
     pub fn is_pre_start_funding(ctx: Context<ReadAccounts>) -> Result<bool> {
-        Ok(Clock::get().unwrap().unix_timestamp
-            < ctx.accounts.authorized_sale_account.start_date_funding)
+        Ok(Clock::get()?.unix_timestamp < ctx.accounts.authorized_sale_account.start_date_funding)
     }
     /// Check if the Funding period has ended
     pub fn is_funding_ended(ctx: Context<ReadAccounts>) -> Result<bool> {
-        Ok(Clock::get().unwrap().unix_timestamp
-            > ctx.accounts.authorized_sale_account.end_date_funding)
+        Ok(Clock::get()?.unix_timestamp > ctx.accounts.authorized_sale_account.end_date_funding)
+    }
+    /// Check if the Funding has ended below the soft cap, so buyers get refunded
+    pub fn is_funding_failed(ctx: Context<ReadAccounts>) -> Result<bool> {
+        Ok(ctx.accounts.authorized_sale_account.is_funding_failed(
+            ctx.accounts.sale_account.total_spend_pay_tokens,
+            Clock::get()?.unix_timestamp,
+        ))
     }
 
     // BusinessLogic
-    /// Calculates how much Payment tokens needed to acquire IDO token allocation
+    /// Calculates how much Payment tokens needed to acquire IDO token allocation, rounded up
     pub fn calculate_max_payment_token(
         ctx: Context<ReadAccounts>,
         _ido_tokens_to_get: u128,
     ) -> Result<u128> {
-        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
-
-        let ido_token_decimal: u128 = 10u128.checked_pow(18 - 2).unwrap();
-        let pay_token_token_decimal: u128 = 10u128.checked_pow(6 - 2).unwrap();
-
-        let _ido_tokens_to_get: u128 = _ido_tokens_to_get.checked_div(ido_token_decimal).unwrap(); // 10000000000000000 / 10 ^ 16 = 1
-
-        let ido_token_price_ratio = authorized_sale_account.ido_token_price_ratio as u128;
-        let _divide_by_ratio = ido_token_price_ratio
-            .checked_mul(pay_token_token_decimal)
-            .unwrap(); // (4 * 10 ^ 3) * 10 ^ 4 = 4 * 10 ^ 7
-
-        let mut _amount_in_pay_token = (_ido_tokens_to_get).checked_mul(_divide_by_ratio).unwrap(); // 1 * 4 * 10 ^ 7 = 4 * 10 ^ 7
-        let ido_token_price_multiplier = authorized_sale_account.ido_token_price_multiplier as u128;
-        _amount_in_pay_token = _amount_in_pay_token
-            .checked_div(ido_token_price_multiplier)
-            .unwrap(); // (4 * 10 ^ 7) / 10 ^ 4 = 4 * 10 ^ 3 USDC tokens
-        Ok(_amount_in_pay_token)
+        ctx.accounts
+            .authorized_sale_account
+            .pay_tokens_for(_ido_tokens_to_get, Rounding::Up)
     }
-    /// Calculate the amount of Ido Tokens bought
+    /// Calculate the amount of Ido Tokens bought, rounded down
     pub fn calculate_ido_tokens_bought(
         ctx: Context<ReadAccounts>,
         _amount_in_pay_token: u128,
     ) -> Result<u128> {
-        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
-
-        let ido_token_decimal: u128 = 10u128.checked_pow(18 - 2).unwrap();
-        let pay_token_token_decimal: u128 = 10u128.checked_pow(6 - 2).unwrap();
-
-        let _amount_in_pay_token = _amount_in_pay_token
-            .checked_mul(authorized_sale_account.ido_token_price_multiplier as u128)
-            .unwrap(); // 250_000_000 * 10_000 = 2_500_000_000_000
-        let _divide_by_ratio = (authorized_sale_account.ido_token_price_ratio as u128)
-            .checked_mul(pay_token_token_decimal)
-            .unwrap(); // 4_000 * 10_000 = 40_000_000
-        let mut _ido_tokens_to_get = _amount_in_pay_token.checked_div(_divide_by_ratio).unwrap(); // 2_500_000_000_000 / 40_000_000 = 62_500
-        _ido_tokens_to_get = _ido_tokens_to_get.checked_mul(ido_token_decimal).unwrap(); // 62_500 * 10_000_000_000_000_000 = 625_000_000_000_000_000_000
-        Ok(_ido_tokens_to_get)
+        ctx.accounts
+            .authorized_sale_account
+            .ido_tokens_for(_amount_in_pay_token, Rounding::Down)
     }
     /// Give the programAddress the ido tokens to be sold
     pub fn fund_to_contract(
-        ctx: Context<AdminOnlyUpdate>,
+        ctx: Context<AdminIdoTokenUpdate>,
         _amount_in_ido_tokens: u128,
     ) -> Result<()> {
-        assert!(ctx.accounts.is_funding_closed());
-        assert!(ctx.accounts.is_funding_not_canceled_by_admin());
-        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
-        let sale_account = &mut ctx.accounts.sale_account;
-        assert_eq!(
-            authorized_sale_account.is_ido_token_funded_to_contract, false,
-            "Already funded tokens"
+        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
+        // Funded first, the vault has to back every purchase up to the hard cap
+        if authorized_sale_account.is_fund_first {
+            require!(
+                _amount_in_ido_tokens >= authorized_sale_account.hard_cap_ido_tokens,
+                SaleError::FundingBelowHardCap
+            );
+        } else {
+            ctx.accounts.is_funding_closed()?;
+        }
+        ctx.accounts.is_funding_not_canceled_by_admin()?;
+        ctx.accounts.is_soft_cap_reached()?;
+        let sale_account = &ctx.accounts.sale_account;
+        require!(
+            !authorized_sale_account.is_ido_token_funded_to_contract,
+            SaleError::AlreadyFunded
         );
-        assert!(
+        require!(
             sale_account.total_allocated_ido_tokens <= _amount_in_ido_tokens,
-            "You should at least match the totalAllocatedIdoTokens"
+            SaleError::FundingBelowAllocatedIdoTokens
+        );
+        require!(
+            ctx.accounts.admin_ido_token_account.amount as u128 >= _amount_in_ido_tokens,
+            SaleError::InsufficientIdoTokens
         );
-        // todo: check if admin have enough tokens to fund to the contract
-        // todo: transfer funds
+        // Transfer the IDO tokens from the admin to the IDO token vault
+        token::transfer(
+            ctx.accounts.into_transfer_to_ido_token_vault_context(),
+            u64::try_from(_amount_in_ido_tokens).map_err(|_| SaleError::MathOverflow)?,
+        )?;
+        let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
         authorized_sale_account.tokens_for_sale = _amount_in_ido_tokens;
         authorized_sale_account.is_ido_token_funded_to_contract = true;
         Ok(())
     }
     /// Buy Tokens, but not really, just transfer the payment tokens to the Contract
     /// and create a receipt that can later be claimed by the buyer
-    pub fn buy(ctx: Context<BuyersOnlyUpdate>, _amount_in_pay_token: u128) -> Result<()> {
-        ctx.accounts.is_funding_open_and_running();
-        ctx.accounts.is_funding_not_canceled_by_admin();
-        // todo: isLotteryPlayedAndAllocationCalculated
-        // todo: onlyWinners
-        let amount_in_pay_token = calculate_max_payment_token(
-            Context::new(
-                &crate::id(),
-                &mut ReadAccounts {
-                    authorized_sale_account: ctx.accounts.authorized_sale_account.clone(),
-                    sale_account: ctx.accounts.sale_account.clone(),
-                    user: ctx.accounts.user.clone(),
-                },
-                &[],
-                std::collections::BTreeMap::new(),
-            ),
-            10000000000000000, // todo: data fron lottery contract
-        )?;
-        let ido_tokens_to_buy = calculate_ido_tokens_bought(
-            Context::new(
-                &crate::id(),
-                &mut ReadAccounts {
-                    authorized_sale_account: ctx.accounts.authorized_sale_account.clone(),
-                    sale_account: ctx.accounts.sale_account.clone(),
-                    user: ctx.accounts.user.clone(),
-                },
-                &[],
-                std::collections::BTreeMap::new(),
-            ),
+    pub fn buy(ctx: Context<BuyersAllocationUpdate>, _amount_in_pay_token: u128) -> Result<()> {
+        ctx.accounts.is_funding_open_and_running()?;
+        ctx.accounts.is_funding_not_canceled_by_admin()?;
+        ctx.accounts.is_lottery_played()?;
+        let ido_tokens_to_buy = record_purchase(
+            &ctx.accounts.authorized_sale_account,
+            &mut ctx.accounts.sale_account,
+            &mut ctx.accounts.buyer_info,
+            ctx.accounts.user_pay_token_account.amount,
             _amount_in_pay_token,
+            ctx.accounts.allocation.ido_tokens_allocation,
         )?;
-        let is_buyer = is_buyer(
-            Context::new(
-                &crate::id(),
-                &mut ReadAccounts {
-                    authorized_sale_account: ctx.accounts.authorized_sale_account.clone(),
-                    sale_account: ctx.accounts.sale_account.clone(),
-                    user: ctx.accounts.user.clone(),
-                },
-                &[],
-                std::collections::BTreeMap::new(),
-            ),
-            *ctx.accounts.user.key,
+        // Get paid in pay tokens
+        token::transfer(
+            ctx.accounts.into_transfer_to_pay_token_vault_context(),
+            u64::try_from(_amount_in_pay_token).map_err(|_| SaleError::MathOverflow)?,
         )?;
-
-        assert!(_amount_in_pay_token > 0, "Amount has to be positive");
-        // todo: check user has enough pay tokens
-        assert!(_amount_in_pay_token <= amount_in_pay_token,
-            "You cannot buy more tokens than is allowed according to your lottery allocation calculation"
-        );
-        let buyer_info = &mut ctx.accounts.buyer_info;
-        let final_spend_pay_tokens = buyer_info
-            .spend_pay_tokens
-            .checked_add(_amount_in_pay_token)
-            .unwrap();
-        assert!(final_spend_pay_tokens <= amount_in_pay_token,
-            "You cannot buy more tokens than is allowed according to your lottery allocation calculation"
-        );
-        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
-        if authorized_sale_account.in_one_transaction {
-            assert!(
-                amount_in_pay_token == _amount_in_pay_token,
-                "You need to buy the entire allocation in one transaction"
-            );
-        }
-        let sale_account = &mut ctx.accounts.sale_account;
-        if buyer_info.spend_pay_tokens == 0 {
-            sale_account.investor_count = sale_account.investor_count.checked_add(1).unwrap();
-        }
-        // todo: get paid in pay tokens
-        sale_account.total_spend_pay_tokens = sale_account
-            .total_spend_pay_tokens
-            .checked_add(_amount_in_pay_token)
-            .unwrap();
-        sale_account.total_allocated_ido_tokens = sale_account
-            .total_allocated_ido_tokens
-            .checked_add(ido_tokens_to_buy)
-            .unwrap();
-
-        buyer_info.spend_pay_tokens = buyer_info
-            .spend_pay_tokens
-            .checked_add(_amount_in_pay_token)
-            .unwrap();
-        buyer_info.ido_tokens_to_get = buyer_info
-            .ido_tokens_to_get
-            .checked_add(ido_tokens_to_buy)
-            .unwrap();
-        if !is_buyer {
-            sale_account.buyers_list.push(*ctx.accounts.user.key);
-        }
         emit!(BoughtIDOTokens {
             buyer: *ctx.accounts.user.key,
             spend_pay_tokens: _amount_in_pay_token,
             ido_tokens_to_get: ido_tokens_to_buy,
-            timestamp: Clock::get().unwrap().unix_timestamp
+            timestamp: Clock::get()?.unix_timestamp
         });
         Ok(())
     }
-    /// After the Funding period, users are allowed to claim their IDO Tokens
-    pub fn claim_tokens(ctx: Context<BuyersOnlyUpdate>) -> Result<()> {
-        ctx.accounts.is_funding_closed();
-        ctx.accounts.is_funding_not_canceled_by_admin();
-        // todo: isLotteryPlayedAndAllocationCalculated
-
-        let is_buyer = is_buyer(
-            Context::new(
-                &crate::id(),
-                &mut ReadAccounts {
-                    authorized_sale_account: ctx.accounts.authorized_sale_account.clone(),
-                    sale_account: ctx.accounts.sale_account.clone(),
-                    user: ctx.accounts.user.clone(),
-                },
-                &[],
-                std::collections::BTreeMap::new(),
-            ),
-            *ctx.accounts.user.key,
+    /// Buy Tokens like `buy`, with the allocation proven against the whitelist merkle root
+    /// instead of an allocation account
+    pub fn buy_with_merkle_proof(
+        ctx: Context<BuyersMerkleProofUpdate>,
+        _amount_in_pay_token: u128,
+        _max_allocation: u128,
+        _proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        ctx.accounts.is_funding_open_and_running()?;
+        ctx.accounts.is_funding_not_canceled_by_admin()?;
+        ctx.accounts.is_whitelisted(_max_allocation, &_proof)?;
+        let ido_tokens_to_buy = record_purchase(
+            &ctx.accounts.authorized_sale_account,
+            &mut ctx.accounts.sale_account,
+            &mut ctx.accounts.buyer_info,
+            ctx.accounts.user_pay_token_account.amount,
+            _amount_in_pay_token,
+            _max_allocation,
         )?;
-        let initial_tokens_to_get = calculate_ido_tokens_bought(
-            Context::new(
-                &crate::id(),
-                &mut ReadAccounts {
-                    authorized_sale_account: ctx.accounts.authorized_sale_account.clone(),
-                    sale_account: ctx.accounts.sale_account.clone(),
-                    user: ctx.accounts.user.clone(),
-                },
-                &[],
-                std::collections::BTreeMap::new(),
-            ),
-            ctx.accounts.buyer_info.spend_pay_tokens,
+        // Get paid in pay tokens
+        token::transfer(
+            ctx.accounts.into_transfer_to_pay_token_vault_context(),
+            u64::try_from(_amount_in_pay_token).map_err(|_| SaleError::MathOverflow)?,
         )?;
+        emit!(BoughtIDOTokens {
+            buyer: *ctx.accounts.user.key,
+            spend_pay_tokens: _amount_in_pay_token,
+            ido_tokens_to_get: ido_tokens_to_buy,
+            timestamp: Clock::get()?.unix_timestamp
+        });
+        Ok(())
+    }
+    /// After the Funding period, users are allowed to claim their IDO Tokens
+    pub fn claim_tokens(ctx: Context<BuyersIdoTokenUpdate>) -> Result<()> {
+        ctx.accounts.is_funding_closed()?;
+        ctx.accounts.is_funding_not_canceled_by_admin()?;
+        ctx.accounts.is_soft_cap_reached()?;
 
         let authorized_sale_account = &ctx.accounts.authorized_sale_account;
-        assert!(
+        require!(
             authorized_sale_account.is_ido_token_funded_to_contract,
-            "Tokens have not been added to the contract YET"
+            SaleError::IdoTokensNotFunded
         );
-        let buyer_info = &mut ctx.accounts.buyer_info;
-        assert!(
+        let buyer_info = &ctx.accounts.buyer_info;
+        require!(
             buyer_info.ido_tokens_claimed < buyer_info.ido_tokens_to_get,
-            "You have already claimed the tokens"
+            SaleError::AlreadyClaimed
         );
-        assert!(
+        require!(
             authorized_sale_account.is_claiming_open,
-            "Cannot claim, you need to wait until claiming is enabled"
-        );
-        assert!(is_buyer, "You are not a buyer");
-
-        let _seconds_in_total_between_start_and_end_date_claiming_tokens = authorized_sale_account
-            .end_date_of_claiming_tokens
-            .checked_sub(authorized_sale_account.start_date_of_claiming_tokens)
-            .unwrap();
-        let mut _ido_tokens_per_second = buyer_info
-            .ido_tokens_to_get
-            .checked_div(_seconds_in_total_between_start_and_end_date_claiming_tokens as u128)
-            .unwrap();
-        let mut _total_tokens_to_get = 0u128;
-
-        // Allocate all the tokens, if current time already surpassed the endDateOfClaimingTokens
-        if Clock::get().unwrap().unix_timestamp
-            > authorized_sale_account.end_date_of_claiming_tokens
-        {
-            _total_tokens_to_get = buyer_info.ido_tokens_to_get;
-        } else if authorized_sale_account.initial_percentage_allocation_ido_tokens > 0 {
-            // Calculates the _totalTokensToGet with this percentage
-            let _initial_tokens_to_get = initial_tokens_to_get
-                .checked_div(100)
-                .unwrap()
-                .checked_mul(
-                    authorized_sale_account.initial_percentage_allocation_ido_tokens as u128,
-                )
-                .unwrap();
-            if Clock::get().unwrap().unix_timestamp
-                >= authorized_sale_account.start_date_of_claiming_tokens
-            {
-                // Removes the initial tokes to get from the total supply tokens to get percentage
-                _ido_tokens_per_second = buyer_info
-                    .ido_tokens_to_get
-                    .checked_sub(_initial_tokens_to_get)
-                    .unwrap()
-                    .checked_div(
-                        _seconds_in_total_between_start_and_end_date_claiming_tokens as u128,
-                    )
-                    .unwrap();
-                // Calculate how many tokens to get since startDateOfClaimingTokens
-                let _seconds_passed_since_start = Clock::get()
-                    .unwrap()
-                    .unix_timestamp
-                    .checked_sub(authorized_sale_account.start_date_of_claiming_tokens)
-                    .unwrap();
-                _total_tokens_to_get = _ido_tokens_per_second
-                    .checked_mul(_seconds_passed_since_start as u128)
-                    .unwrap();
-            }
-            // Add the initial tokens to get to the tokens that can be claimed
-            _total_tokens_to_get = _total_tokens_to_get
-                .checked_add(_initial_tokens_to_get)
-                .unwrap();
-        } else {
-            // End date has not yet been reached
-            let _seconds_passed_since_start = Clock::get()
-                .unwrap()
-                .unix_timestamp
-                .checked_sub(authorized_sale_account.start_date_of_claiming_tokens)
-                .unwrap();
-            _total_tokens_to_get = _ido_tokens_per_second
-                .checked_mul(_seconds_passed_since_start as u128)
-                .unwrap();
-        }
-        // Subtract previous already claimed tokens
-        _total_tokens_to_get = _total_tokens_to_get
-            .checked_sub(buyer_info.ido_tokens_claimed)
-            .unwrap();
-        // todo: transfer the idoTokens to the msg.sender from the contract
+            SaleError::ClaimingNotOpen
+        );
+        require!(buyer_info.ido_tokens_to_get > 0, SaleError::NotABuyer);
+
+        let _total_tokens_to_get = authorized_sale_account
+            .vesting_schedule(&ctx.accounts.tranche_schedule, buyer_info)
+            .releasable(Clock::get()?.unix_timestamp, buyer_info)?;
+        require!(_total_tokens_to_get > 0, SaleError::NothingToClaim);
+        // Transfer the IDO tokens from the IDO token vault to the buyer
+        let authorized_sale_account_key = authorized_sale_account.key();
+        let ido_token_vault_seeds = &[
+            b"ido-token-vault".as_ref(),
+            authorized_sale_account_key.as_ref(),
+            &[authorized_sale_account.ido_token_vault_bump],
+        ];
+        token::transfer(
+            ctx.accounts
+                .into_transfer_from_ido_token_vault_context()
+                .with_signer(&[&ido_token_vault_seeds[..]]),
+            u64::try_from(_total_tokens_to_get).map_err(|_| SaleError::MathOverflow)?,
+        )?;
         // Update mapping
+        let buyer_info = &mut ctx.accounts.buyer_info;
         buyer_info.ido_tokens_claimed = buyer_info
             .ido_tokens_claimed
             .checked_add(_total_tokens_to_get)
-            .unwrap();
+            .ok_or(SaleError::MathOverflow)?;
         emit!(ClaimedIDOTokens {
             buyer: *ctx.accounts.user.key,
             ido_tokens_to_get: _total_tokens_to_get
@@ -584,193 +906,1165 @@ pub mod solana_mangamon_sale {
     }
     /// Withdraw Pay Tokens from contract Only withdraw Pay tokens after the funding has ended
     pub fn withdraw_pay_tokens(
-        ctx: Context<AdminOnlyUpdate>,
+        ctx: Context<AdminPayTokenUpdate>,
         _pay_tokens_to_withdraw: u128,
     ) -> Result<()> {
-        assert!(ctx.accounts.is_funding_closed());
-        // todo: withdraw pay tokens
+        ctx.accounts.is_funding_closed()?;
+        // Keep the pay tokens in the vault, so buyers can be refunded
+        ctx.accounts.is_funding_not_canceled_by_admin()?;
+        ctx.accounts.is_soft_cap_reached()?;
+        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
+        let sale_account = &ctx.accounts.sale_account;
+        require!(_pay_tokens_to_withdraw > 0, SaleError::AmountNotPositive);
+        let _withdrawable_pay_tokens = sale_account
+            .total_spend_pay_tokens
+            .checked_sub(sale_account.total_withdrawn_pay_tokens)
+            .ok_or(SaleError::MathOverflow)?;
+        require!(
+            _pay_tokens_to_withdraw <= _withdrawable_pay_tokens,
+            SaleError::WithdrawExceedsRaisedPayTokens
+        );
+        // Transfer the pay tokens from the pay token vault to the treasury
+        let authorized_sale_account_key = authorized_sale_account.key();
+        let pay_token_vault_seeds = &[
+            b"pay-token-vault".as_ref(),
+            authorized_sale_account_key.as_ref(),
+            &[authorized_sale_account.pay_token_vault_bump],
+        ];
+        token::transfer(
+            ctx.accounts
+                .into_transfer_from_pay_token_vault_context()
+                .with_signer(&[&pay_token_vault_seeds[..]]),
+            u64::try_from(_pay_tokens_to_withdraw).map_err(|_| SaleError::MathOverflow)?,
+        )?;
+        let sale_account = &mut ctx.accounts.sale_account;
+        sale_account.total_withdrawn_pay_tokens = sale_account
+            .total_withdrawn_pay_tokens
+            .checked_add(_pay_tokens_to_withdraw)
+            .ok_or(SaleError::MathOverflow)?;
+        emit!(WithdrewPayTokens {
+            admin: *ctx.accounts.admin.key,
+            treasury_pay_token_account: ctx.accounts.treasury_pay_token_account.key(),
+            pay_tokens_withdrawn: _pay_tokens_to_withdraw
+        });
         Ok(())
     }
-    /// Withdraw unsold IDO tokens
+    /// Withdraw unsold IDO tokens, or every IDO token left once the sale is canceled or
+    /// has failed to reach the soft cap
     pub fn withdraw_unsold_ido_tokens(
-        ctx: Context<AdminOnlyUpdate>,
+        ctx: Context<AdminIdoTokenUpdate>,
         _ido_tokens_to_withdraw: u128,
     ) -> Result<()> {
-        assert!(ctx.accounts.is_funding_closed());
-        // todo: if IDO token is available, withdraw ido tokens
+        // A canceled sale can be wound down right away
+        if !ctx.accounts.authorized_sale_account.is_funding_canceled {
+            ctx.accounts.is_funding_closed()?;
+        }
+        let is_funding_canceled_or_failed = ctx.accounts.is_funding_canceled_or_failed()?;
+        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
+        let sale_account = &ctx.accounts.sale_account;
+        require!(
+            authorized_sale_account.is_ido_token_funded_to_contract,
+            SaleError::IdoTokensNotFunded
+        );
+        require!(_ido_tokens_to_withdraw > 0, SaleError::AmountNotPositive);
+        // Tokens allocated to buyers stay in the vault until claimed, unless the buyers
+        // get refunded instead
+        let _allocated_ido_tokens = if is_funding_canceled_or_failed {
+            0
+        } else {
+            sale_account.total_allocated_ido_tokens
+        };
+        let _unsold_ido_tokens = authorized_sale_account
+            .tokens_for_sale
+            .checked_sub(_allocated_ido_tokens)
+            .ok_or(SaleError::MathOverflow)?
+            .checked_sub(sale_account.total_withdrawn_unsold_ido_tokens)
+            .ok_or(SaleError::MathOverflow)?;
+        require!(
+            _ido_tokens_to_withdraw <= _unsold_ido_tokens,
+            SaleError::WithdrawExceedsUnsoldIdoTokens
+        );
+        // Transfer the IDO tokens from the IDO token vault back to the admin
+        let authorized_sale_account_key = authorized_sale_account.key();
+        let ido_token_vault_seeds = &[
+            b"ido-token-vault".as_ref(),
+            authorized_sale_account_key.as_ref(),
+            &[authorized_sale_account.ido_token_vault_bump],
+        ];
+        token::transfer(
+            ctx.accounts
+                .into_transfer_from_ido_token_vault_context()
+                .with_signer(&[&ido_token_vault_seeds[..]]),
+            u64::try_from(_ido_tokens_to_withdraw).map_err(|_| SaleError::MathOverflow)?,
+        )?;
+        let sale_account = &mut ctx.accounts.sale_account;
+        sale_account.total_withdrawn_unsold_ido_tokens = sale_account
+            .total_withdrawn_unsold_ido_tokens
+            .checked_add(_ido_tokens_to_withdraw)
+            .ok_or(SaleError::MathOverflow)?;
+        emit!(WithdrewUnsoldIdoTokens {
+            admin: *ctx.accounts.admin.key,
+            ido_tokens_withdrawn: _ido_tokens_to_withdraw
+        });
         Ok(())
     }
-    /// Cancels the entire sale
+    /// Cancels the entire sale, as long as no pay tokens have been withdrawn and claiming
+    /// has never been opened, so the buyers can still be refunded in full
     pub fn cancel_ido_sale(ctx: Context<AdminOnlyUpdate>) -> Result<()> {
+        require!(
+            ctx.accounts.sale_account.total_withdrawn_pay_tokens == 0
+                && !ctx.accounts.authorized_sale_account.has_claiming_opened,
+            SaleError::SaleAlreadySettled
+        );
         let authorized_sale_account = &mut ctx.accounts.authorized_sale_account;
         authorized_sale_account.is_funding_canceled = true;
         Ok(())
     }
-    /// Let users claim his payed tokens if ido sale is canceled
-    pub fn claim_payed_tokens_on_ido_cancel(ctx: Context<BuyersOnlyUpdate>) -> Result<()> {
-        ctx.accounts.is_funding_canceled_by_admin();
-        let buyer_info = &mut ctx.accounts.buyer_info;
-        assert_eq!(
-            buyer_info.has_claimed_pay_tokens, false,
-            "You have been refunded already"
+    /// Let users claim his payed tokens if ido sale is canceled, or has failed to reach
+    /// the soft cap
+    pub fn claim_payed_tokens_on_ido_cancel(ctx: Context<BuyersPayTokenUpdate>) -> Result<()> {
+        ctx.accounts.is_funding_canceled_or_failed()?;
+        let authorized_sale_account = &ctx.accounts.authorized_sale_account;
+        let buyer_info = &ctx.accounts.buyer_info;
+        require!(
+            !buyer_info.has_claimed_pay_tokens,
+            SaleError::AlreadyRefunded
         );
         let _pay_tokens_to_return = buyer_info.spend_pay_tokens;
+        // Transfer the pay tokens from the pay token vault back to the buyer
+        let authorized_sale_account_key = authorized_sale_account.key();
+        let pay_token_vault_seeds = &[
+            b"pay-token-vault".as_ref(),
+            authorized_sale_account_key.as_ref(),
+            &[authorized_sale_account.pay_token_vault_bump],
+        ];
+        token::transfer(
+            ctx.accounts
+                .into_transfer_from_pay_token_vault_context()
+                .with_signer(&[&pay_token_vault_seeds[..]]),
+            u64::try_from(_pay_tokens_to_return).map_err(|_| SaleError::MathOverflow)?,
+        )?;
         // Update states
+        let sale_account = &mut ctx.accounts.sale_account;
+        sale_account.total_spend_pay_tokens = sale_account
+            .total_spend_pay_tokens
+            .checked_sub(_pay_tokens_to_return)
+            .ok_or(SaleError::MathOverflow)?;
+        let buyer_info = &mut ctx.accounts.buyer_info;
         buyer_info.spend_pay_tokens = 0;
         buyer_info.has_claimed_pay_tokens = true;
-        // todo: transfer pay tokens
+        emit!(RefundedPayTokens {
+            buyer: *ctx.accounts.user.key,
+            pay_tokens_refunded: _pay_tokens_to_return
+        });
         Ok(())
     }
 }
 
-/// Validation struct for initialize
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(init, payer = user, space = 8 + 101)]
-    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
-    #[account(init, payer = user, space = 8 + 3244)]
-    pub sale_account: Account<'info, SaleAccount>,
-    #[account(mut)]
+/// The hash of `slot` in the SlotHashes sysvar data, a length followed by (slot, hash)
+/// entries, if it is one of the recent slots it still holds
+fn find_slot_hash(slot_hashes_data: &[u8], slot: u64) -> Option<&[u8]> {
+    let len = u64::from_le_bytes(slot_hashes_data.get(..8)?.try_into().ok()?) as usize;
+    slot_hashes_data
+        .get(8..)?
+        .chunks_exact(40)
+        .take(len)
+        .find(|entry| entry[..8] == slot.to_le_bytes())
+        .map(|entry| &entry[8..])
+}
+
+/// Check a purchase against the buyer's allocation and record it on the sale and the
+/// buyer, returning the IDO tokens bought. Shared by `buy` and `buy_with_merkle_proof`,
+/// which transfer the payment themselves
+fn record_purchase<'info>(
+    authorized_sale_account: &Account<'info, AuthorizedSaleAccount>,
+    sale_account: &mut Account<'info, SaleAccount>,
+    buyer_info: &mut Account<'info, BuyerInfo>,
+    _user_pay_token_balance: u64,
+    _amount_in_pay_token: u128,
+    _ido_tokens_allocation: u128,
+) -> Result<u128> {
+    let remaining_allocation = _ido_tokens_allocation.saturating_sub(buyer_info.ido_tokens_to_get);
+
+    if authorized_sale_account.is_fund_first {
+        require!(
+            authorized_sale_account.is_ido_token_funded_to_contract,
+            SaleError::IdoTokensNotFunded
+        );
+    }
+    require!(_amount_in_pay_token > 0, SaleError::AmountNotPositive);
+    require!(
+        _user_pay_token_balance as u128 >= _amount_in_pay_token,
+        SaleError::InsufficientPayTokens
+    );
+    let ido_tokens_to_buy = authorized_sale_account
+        .ido_tokens_bought_within(_amount_in_pay_token, remaining_allocation)?;
+    // Too few pay tokens to buy a single IDO token base unit
+    require!(ido_tokens_to_buy > 0, SaleError::AmountNotPositive);
+    if authorized_sale_account.in_one_transaction {
+        let amount_in_pay_token =
+            authorized_sale_account.pay_tokens_for(_ido_tokens_allocation, Rounding::Up)?;
+        require!(
+            amount_in_pay_token == _amount_in_pay_token,
+            SaleError::EntireAllocationRequired
+        );
+    }
+    let total_allocated_ido_tokens = sale_account
+        .total_allocated_ido_tokens
+        .checked_add(ido_tokens_to_buy)
+        .ok_or(SaleError::MathOverflow)?;
+    require!(
+        total_allocated_ido_tokens <= authorized_sale_account.hard_cap_ido_tokens,
+        SaleError::HardCapExceeded
+    );
+    if buyer_info.spend_pay_tokens == 0 {
+        sale_account.investor_count = sale_account
+            .investor_count
+            .checked_add(1)
+            .ok_or(SaleError::MathOverflow)?;
+    }
+    sale_account.total_spend_pay_tokens = sale_account
+        .total_spend_pay_tokens
+        .checked_add(_amount_in_pay_token)
+        .ok_or(SaleError::MathOverflow)?;
+    sale_account.total_allocated_ido_tokens = total_allocated_ido_tokens;
+
+    buyer_info.spend_pay_tokens = buyer_info
+        .spend_pay_tokens
+        .checked_add(_amount_in_pay_token)
+        .ok_or(SaleError::MathOverflow)?;
+    buyer_info.ido_tokens_to_get = buyer_info
+        .ido_tokens_to_get
+        .checked_add(ido_tokens_to_buy)
+        .ok_or(SaleError::MathOverflow)?;
+    Ok(ido_tokens_to_buy)
+}
+
+/// Validation struct for initialize
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init, payer = user, space = 8 + 439)]
+    pub authorized_sale_account: Box<Account<'info, AuthorizedSaleAccount>>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 88,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump
+    )]
+    pub sale_account: Box<Account<'info, SaleAccount>>,
+    pub pay_token_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"pay-token-vault", authorized_sale_account.key().as_ref()],
+        bump,
+        token::mint = pay_token_mint,
+        token::authority = pay_token_vault
+    )]
+    pub pay_token_vault: Box<Account<'info, TokenAccount>>,
+    pub ido_token_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"ido-token-vault", authorized_sale_account.key().as_ref()],
+        bump,
+        token::mint = ido_token_mint,
+        token::authority = ido_token_vault
+    )]
+    pub ido_token_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 4 + MAX_TRANCHES * 10,
+        seeds = [b"tranche-schedule", authorized_sale_account.key().as_ref()],
+        bump
+    )]
+    pub tranche_schedule: Box<Account<'info, TrancheSchedule>>,
+    #[account(mut)]
     pub user: Signer<'info>,
     pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 /// Validation struct for creat_buyer_info
 #[derive(Accounts)]
 pub struct CreatBuyerInfo<'info> {
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
     #[account(mut)]
     pub user: Signer<'info>,
     #[account(
         init,
         payer = user,
-        space = 8 + 50,
-        seeds = [b"buyer-info", user.key().as_ref()],
+        space = 8 + 69,
+        seeds = [b"buyer-info", authorized_sale_account.key().as_ref(), user.key().as_ref()],
         bump
     )]
     pub buyer_info: Account<'info, BuyerInfo>,
     pub system_program: Program<'info, System>,
 }
 
+/// Validation struct for add_to_buyer_registry
+#[derive(Accounts)]
+#[instruction(_buyer: Pubkey, _page_index: u64)]
+pub struct BuyerRegistryUpdate<'info> {
+    #[account(has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    #[account(
+        mut,
+        seeds = [
+            b"buyer-info",
+            authorized_sale_account.key().as_ref(),
+            _buyer.as_ref()
+        ],
+        bump = buyer_info.bump
+    )]
+    pub buyer_info: Account<'info, BuyerInfo>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + 4 + BUYER_REGISTRY_PAGE_SIZE * 32,
+        seeds = [
+            b"buyer-registry",
+            authorized_sale_account.key().as_ref(),
+            _page_index.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub buyer_registry_page: Account<'info, BuyerRegistryPage>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 /// Validation struct for updating fields by admin only
 #[derive(Accounts)]
 pub struct AdminOnlyUpdate<'info> {
-    #[account(mut, has_one = admin)]
+    #[account(mut, has_one = admin, has_one = sale_account)]
     pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
     pub sale_account: Account<'info, SaleAccount>,
     pub admin: Signer<'info>,
 }
 impl<'info> AdminOnlyUpdate<'info> {
+    /// Check if the Funding has not started yet
+    pub fn is_pre_start_funding(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
+            now_ts < self.authorized_sale_account.start_date_funding,
+            SaleError::FundingAlreadyStarted
+        );
+        Ok(())
+    }
     /// Check if the Funding has ended
-    pub fn is_funding_closed(&self) -> bool {
-        let now_ts = Clock::get().unwrap().unix_timestamp;
-        assert!(
+    pub fn is_funding_closed(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
             now_ts > self.authorized_sale_account.end_date_funding,
-            "The Funding Period has not ended"
+            SaleError::FundingNotEnded
         );
-        true
+        Ok(())
     }
     /// Check if the Funding has not been canceled
-    pub fn is_funding_not_canceled_by_admin(&self) -> bool {
-        assert_eq!(
-            self.authorized_sale_account.is_funding_canceled, false,
-            "Funding has been canceled"
+    pub fn is_funding_not_canceled_by_admin(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_canceled,
+            SaleError::FundingCanceled
         );
-        true
+        Ok(())
     }
 }
 
-/// Validation struct for updating fields of SaleAccount and Buyer's info with reference to the AuthorizedSaleAccount
+/// Validation struct for admin moving IDO tokens between their own token account and the IDO token vault
 #[derive(Accounts)]
-pub struct BuyersOnlyUpdate<'info> {
+pub struct AdminIdoTokenUpdate<'info> {
+    #[account(mut, has_one = admin, has_one = sale_account)]
     pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"ido-token-vault", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.ido_token_vault_bump
+    )]
+    pub ido_token_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = authorized_sale_account.ido_token_mint,
+        token::authority = admin
+    )]
+    pub admin_ido_token_account: Account<'info, TokenAccount>,
+    pub admin: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+impl<'info> AdminIdoTokenUpdate<'info> {
+    /// Check if the Funding has ended
+    pub fn is_funding_closed(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
+            now_ts > self.authorized_sale_account.end_date_funding,
+            SaleError::FundingNotEnded
+        );
+        Ok(())
+    }
+    /// Check if the Funding has not been canceled
+    pub fn is_funding_not_canceled_by_admin(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_canceled,
+            SaleError::FundingCanceled
+        );
+        Ok(())
+    }
+    /// Check if the Funding has not failed to reach the soft cap
+    pub fn is_soft_cap_reached(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_failed(
+                self.sale_account.total_spend_pay_tokens,
+                Clock::get()?.unix_timestamp
+            ),
+            SaleError::SoftCapNotReached
+        );
+        Ok(())
+    }
+    /// Whether the Funding has been canceled, or has ended below the soft cap, so no IDO
+    /// tokens are owed to the buyers
+    pub fn is_funding_canceled_or_failed(&self) -> Result<bool> {
+        Ok(self.authorized_sale_account.is_funding_canceled
+            || self.authorized_sale_account.is_funding_failed(
+                self.sale_account.total_spend_pay_tokens,
+                Clock::get()?.unix_timestamp,
+            ))
+    }
+    /// Transfer context from the admin's IDO token account into the IDO token vault
+    pub fn into_transfer_to_ido_token_vault_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.admin_ido_token_account.to_account_info(),
+            to: self.ido_token_vault.to_account_info(),
+            authority: self.admin.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+    /// Transfer context from the IDO token vault back into the admin's IDO token account
+    pub fn into_transfer_from_ido_token_vault_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.ido_token_vault.to_account_info(),
+            to: self.admin_ido_token_account.to_account_info(),
+            authority: self.ido_token_vault.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+/// Validation struct for admin moving pay tokens from the pay token vault to a treasury token account
+#[derive(Accounts)]
+pub struct AdminPayTokenUpdate<'info> {
+    #[account(mut, has_one = admin, has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"pay-token-vault", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.pay_token_vault_bump
+    )]
+    pub pay_token_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = authorized_sale_account.pay_token_mint)]
+    pub treasury_pay_token_account: Account<'info, TokenAccount>,
+    pub admin: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+impl<'info> AdminPayTokenUpdate<'info> {
+    /// Check if the Funding has ended
+    pub fn is_funding_closed(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
+            now_ts > self.authorized_sale_account.end_date_funding,
+            SaleError::FundingNotEnded
+        );
+        Ok(())
+    }
+    /// Check if the Funding has not been canceled
+    pub fn is_funding_not_canceled_by_admin(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_canceled,
+            SaleError::FundingCanceled
+        );
+        Ok(())
+    }
+    /// Check if the Funding has not failed to reach the soft cap
+    pub fn is_soft_cap_reached(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_failed(
+                self.sale_account.total_spend_pay_tokens,
+                Clock::get()?.unix_timestamp
+            ),
+            SaleError::SoftCapNotReached
+        );
+        Ok(())
+    }
+    /// Transfer context from the pay token vault into the treasury token account
+    pub fn into_transfer_from_pay_token_vault_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.pay_token_vault.to_account_info(),
+            to: self.treasury_pay_token_account.to_account_info(),
+            authority: self.pay_token_vault.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+/// Validation struct for buyers paying for IDO tokens within their allocation
+#[derive(Accounts)]
+pub struct BuyersAllocationUpdate<'info> {
+    #[account(has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    #[account(
+        mut,
+        seeds = [
+            b"buyer-info",
+            authorized_sale_account.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump = buyer_info.bump
+    )]
+    pub buyer_info: Account<'info, BuyerInfo>,
+    #[account(
+        seeds = [b"allocation", authorized_sale_account.key().as_ref(), user.key().as_ref()],
+        bump = allocation.bump
+    )]
+    pub allocation: Account<'info, Allocation>,
+    #[account(
+        mut,
+        seeds = [b"pay-token-vault", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.pay_token_vault_bump
+    )]
+    pub pay_token_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = authorized_sale_account.pay_token_mint,
+        token::authority = user
+    )]
+    pub user_pay_token_account: Account<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+impl<'info> BuyersAllocationUpdate<'info> {
+    /// Check if the Funding period is open
+    pub fn is_funding_open_and_running(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
+            now_ts >= self.authorized_sale_account.start_date_funding
+                && now_ts <= self.authorized_sale_account.end_date_funding,
+            SaleError::FundingNotOpen
+        );
+        Ok(())
+    }
+    /// Check if the Funding has not been canceled
+    pub fn is_funding_not_canceled_by_admin(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_canceled,
+            SaleError::FundingCanceled
+        );
+        Ok(())
+    }
+    /// Check if the lottery, when one has been set up, has been played, so allocations are
+    /// final. Sales without a lottery only use the allocations written by the authority
+    pub fn is_lottery_played(&self) -> Result<()> {
+        require!(
+            self.authorized_sale_account.number_of_lottery_winners == 0
+                || self.authorized_sale_account.is_lottery_played,
+            SaleError::LotteryNotPlayed
+        );
+        Ok(())
+    }
+    /// Transfer context from the buyer's pay token account into the pay token vault
+    pub fn into_transfer_to_pay_token_vault_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_pay_token_account.to_account_info(),
+            to: self.pay_token_vault.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+/// Validation struct for whitelisted buyers paying for IDO tokens with a merkle proof
+#[derive(Accounts)]
+pub struct BuyersMerkleProofUpdate<'info> {
+    #[account(has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
     pub sale_account: Account<'info, SaleAccount>,
-    #[account(mut, seeds = [b"buyer-info", user.key().as_ref()], bump = buyer_info.bump)]
+    #[account(
+        mut,
+        seeds = [
+            b"buyer-info",
+            authorized_sale_account.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump = buyer_info.bump
+    )]
     pub buyer_info: Account<'info, BuyerInfo>,
+    #[account(
+        mut,
+        seeds = [b"pay-token-vault", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.pay_token_vault_bump
+    )]
+    pub pay_token_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = authorized_sale_account.pay_token_mint,
+        token::authority = user
+    )]
+    pub user_pay_token_account: Account<'info, TokenAccount>,
     pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
 }
-impl<'info> BuyersOnlyUpdate<'info> {
+impl<'info> BuyersMerkleProofUpdate<'info> {
     /// Check if the Funding period is open
-    pub fn is_funding_open_and_running(&self) -> bool {
-        let now_ts = Clock::get().unwrap().unix_timestamp;
-        assert!(
+    pub fn is_funding_open_and_running(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
             now_ts >= self.authorized_sale_account.start_date_funding
                 && now_ts <= self.authorized_sale_account.end_date_funding,
-            "The Funding Period is not Open"
+            SaleError::FundingNotOpen
+        );
+        Ok(())
+    }
+    /// Check if the Funding has not been canceled
+    pub fn is_funding_not_canceled_by_admin(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_canceled,
+            SaleError::FundingCanceled
+        );
+        Ok(())
+    }
+    /// Check the proof of (buyer, max allocation) against the whitelist merkle root.
+    /// Leaves and nodes are hashed with distinct prefixes, and node pairs are sorted
+    pub fn is_whitelisted(&self, _max_allocation: u128, _proof: &[[u8; 32]]) -> Result<()> {
+        let mut computed_hash = hashv(&[
+            &[0u8],
+            self.user.key.as_ref(),
+            &_max_allocation.to_le_bytes(),
+        ])
+        .to_bytes();
+        for proof_element in _proof {
+            computed_hash = if computed_hash <= *proof_element {
+                hashv(&[&[1u8], &computed_hash, proof_element]).to_bytes()
+            } else {
+                hashv(&[&[1u8], proof_element, &computed_hash]).to_bytes()
+            };
+        }
+        require!(
+            computed_hash == self.authorized_sale_account.allocation_merkle_root,
+            SaleError::InvalidMerkleProof
+        );
+        Ok(())
+    }
+    /// Transfer context from the buyer's pay token account into the pay token vault
+    pub fn into_transfer_to_pay_token_vault_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_pay_token_account.to_account_info(),
+            to: self.pay_token_vault.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+/// Validation struct for buyers getting their pay tokens back from the pay token vault
+#[derive(Accounts)]
+pub struct BuyersPayTokenUpdate<'info> {
+    #[account(has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    #[account(
+        mut,
+        seeds = [
+            b"buyer-info",
+            authorized_sale_account.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump = buyer_info.bump
+    )]
+    pub buyer_info: Account<'info, BuyerInfo>,
+    #[account(
+        mut,
+        seeds = [b"pay-token-vault", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.pay_token_vault_bump
+    )]
+    pub pay_token_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = authorized_sale_account.pay_token_mint,
+        token::authority = user
+    )]
+    pub user_pay_token_account: Account<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+impl<'info> BuyersPayTokenUpdate<'info> {
+    /// Check if the Funding has been canceled, or has ended below the soft cap
+    pub fn is_funding_canceled_or_failed(&self) -> Result<()> {
+        let is_funding_failed = self.authorized_sale_account.is_funding_failed(
+            self.sale_account.total_spend_pay_tokens,
+            Clock::get()?.unix_timestamp,
+        );
+        require!(
+            self.authorized_sale_account.is_funding_canceled || is_funding_failed,
+            SaleError::FundingNotCanceled
+        );
+        Ok(())
+    }
+    /// Transfer context from the pay token vault back into the buyer's pay token account
+    pub fn into_transfer_from_pay_token_vault_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.pay_token_vault.to_account_info(),
+            to: self.user_pay_token_account.to_account_info(),
+            authority: self.pay_token_vault.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+/// Validation struct for the admin setting the unlock table
+#[derive(Accounts)]
+pub struct AdminTrancheScheduleUpdate<'info> {
+    #[account(has_one = admin)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"tranche-schedule", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.tranche_schedule_bump
+    )]
+    pub tranche_schedule: Account<'info, TrancheSchedule>,
+    pub admin: Signer<'info>,
+}
+
+/// Validation struct for the admin updating a buyer's info
+#[derive(Accounts)]
+#[instruction(_buyer: Pubkey)]
+pub struct AdminBuyerInfoUpdate<'info> {
+    #[account(has_one = admin)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        mut,
+        seeds = [
+            b"buyer-info",
+            authorized_sale_account.key().as_ref(),
+            _buyer.as_ref()
+        ],
+        bump = buyer_info.bump
+    )]
+    pub buyer_info: Account<'info, BuyerInfo>,
+    pub admin: Signer<'info>,
+}
+
+/// Validation struct for the admin or the allocation authority writing a buyer's allocation
+#[derive(Accounts)]
+#[instruction(_buyer: Pubkey)]
+pub struct AllocationAuthorityUpdate<'info> {
+    #[account(
+        constraint = authority.key() == authorized_sale_account.admin
+            || authority.key() == authorized_sale_account.allocation_authority
+            @ SaleError::NotAllocationAuthority
+    )]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + 17,
+        seeds = [b"allocation", authorized_sale_account.key().as_ref(), _buyer.as_ref()],
+        bump
+    )]
+    pub allocation: Account<'info, Allocation>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Validation struct for users registering for the lottery
+#[derive(Accounts)]
+pub struct LotteryRegistration<'info> {
+    #[account(has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 10,
+        seeds = [b"lottery-ticket", authorized_sale_account.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub lottery_ticket: Account<'info, LotteryTicket>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+impl<'info> LotteryRegistration<'info> {
+    /// Check if the lottery registration is open
+    pub fn is_lottery_registration_open(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
+            now_ts >= self.authorized_sale_account.start_date_lottery_registration
+                && now_ts <= self.authorized_sale_account.end_date_lottery_registration,
+            SaleError::LotteryRegistrationNotOpen
+        );
+        Ok(())
+    }
+}
+
+/// Validation struct for the admin revealing the lottery secret
+#[derive(Accounts)]
+pub struct AdminLotteryReveal<'info> {
+    #[account(mut, has_one = admin)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    pub admin: Signer<'info>,
+}
+impl<'info> AdminLotteryReveal<'info> {
+    /// Check if the lottery registration has ended
+    pub fn is_lottery_registration_closed(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
+            now_ts > self.authorized_sale_account.end_date_lottery_registration,
+            SaleError::LotteryRegistrationNotEnded
+        );
+        Ok(())
+    }
+}
+
+/// Validation struct for anyone drawing the lottery
+#[derive(Accounts)]
+pub struct LotteryDraw<'info> {
+    #[account(mut, has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    /// CHECK: only read for the hash of the draw slot, the address pins it to the SlotHashes sysvar
+    #[account(address = slot_hashes::ID)]
+    pub recent_slot_hashes: UncheckedAccount<'info>,
+}
+impl<'info> LotteryDraw<'info> {
+    /// Check if the lottery registration has ended
+    pub fn is_lottery_registration_closed(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
+            now_ts > self.authorized_sale_account.end_date_lottery_registration,
+            SaleError::LotteryRegistrationNotEnded
         );
-        true
+        Ok(())
     }
-    /// Check if the Funding has been canceled
-    pub fn is_funding_canceled_by_admin(&self) -> bool {
-        assert_eq!(
-            self.authorized_sale_account.is_funding_canceled, true,
-            "Funding has not been canceled"
+}
+
+/// Validation struct for marking a lottery winner and writing their allocation
+#[derive(Accounts)]
+#[instruction(_buyer: Pubkey)]
+pub struct LotteryWinnerUpdate<'info> {
+    #[account(has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    #[account(
+        mut,
+        seeds = [b"lottery-ticket", authorized_sale_account.key().as_ref(), _buyer.as_ref()],
+        bump = lottery_ticket.bump
+    )]
+    pub lottery_ticket: Account<'info, LotteryTicket>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + 17,
+        seeds = [b"allocation", authorized_sale_account.key().as_ref(), _buyer.as_ref()],
+        bump
+    )]
+    pub allocation: Account<'info, Allocation>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+impl<'info> LotteryWinnerUpdate<'info> {
+    /// Check if the lottery has been played
+    pub fn is_lottery_played(&self) -> Result<()> {
+        require!(
+            self.authorized_sale_account.is_lottery_played,
+            SaleError::LotteryNotPlayed
         );
-        true
+        Ok(())
+    }
+    /// Number of winners, which can't be more than the tickets registered
+    pub fn winners_count(&self) -> u64 {
+        self.authorized_sale_account
+            .number_of_lottery_winners
+            .min(self.sale_account.lottery_ticket_count)
     }
+    /// The seed picks a random starting ticket, the winners are the tickets following it
+    pub fn is_winning_ticket(&self) -> Result<bool> {
+        let lottery_ticket_count = u128::from(self.sale_account.lottery_ticket_count);
+        let mut offset_bytes = [0u8; 16];
+        offset_bytes.copy_from_slice(&self.authorized_sale_account.lottery_seed[..16]);
+        let offset = u128::from_le_bytes(offset_bytes)
+            .checked_rem(lottery_ticket_count)
+            .ok_or(SaleError::MathOverflow)?;
+        let position = (u128::from(self.lottery_ticket.ticket_number) + lottery_ticket_count
+            - offset)
+            % lottery_ticket_count;
+        Ok(position < u128::from(self.winners_count()))
+    }
+}
+
+/// Validation struct for buyers claiming IDO tokens from the IDO token vault into their associated token account
+#[derive(Accounts)]
+pub struct BuyersIdoTokenUpdate<'info> {
+    #[account(has_one = sale_account)]
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
+    pub sale_account: Account<'info, SaleAccount>,
+    #[account(
+        mut,
+        seeds = [
+            b"buyer-info",
+            authorized_sale_account.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump = buyer_info.bump
+    )]
+    pub buyer_info: Account<'info, BuyerInfo>,
+    #[account(
+        seeds = [b"tranche-schedule", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.tranche_schedule_bump
+    )]
+    pub tranche_schedule: Account<'info, TrancheSchedule>,
+    #[account(address = authorized_sale_account.ido_token_mint)]
+    pub ido_token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"ido-token-vault", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.ido_token_vault_bump
+    )]
+    pub ido_token_vault: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = ido_token_mint,
+        associated_token::authority = user
+    )]
+    pub user_ido_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+impl<'info> BuyersIdoTokenUpdate<'info> {
     /// Check if the Funding has ended
-    pub fn is_funding_closed(&self) -> bool {
-        let now_ts = Clock::get().unwrap().unix_timestamp;
-        assert!(
+    pub fn is_funding_closed(&self) -> Result<()> {
+        let now_ts = Clock::get()?.unix_timestamp;
+        require!(
             now_ts > self.authorized_sale_account.end_date_funding,
-            "The Funding Period has not ended"
+            SaleError::FundingNotEnded
         );
-        true
+        Ok(())
     }
     /// Check if the Funding has not been canceled
-    pub fn is_funding_not_canceled_by_admin(&self) -> bool {
-        assert_eq!(
-            self.authorized_sale_account.is_funding_canceled, false,
-            "Funding has been canceled"
-        );
-        true
-    }
-    /// Checks if buyer's in buyer list
-    pub fn is_buyer(&self, _buyer: Pubkey) -> bool {
-        let buyers_list = &self.sale_account.buyers_list;
-        for buyer in buyers_list {
-            if buyer.to_bytes() == _buyer.to_bytes() {
-                return true;
-            }
-        }
-        false
+    pub fn is_funding_not_canceled_by_admin(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_canceled,
+            SaleError::FundingCanceled
+        );
+        Ok(())
+    }
+    /// Check if the Funding has not failed to reach the soft cap
+    pub fn is_soft_cap_reached(&self) -> Result<()> {
+        require!(
+            !self.authorized_sale_account.is_funding_failed(
+                self.sale_account.total_spend_pay_tokens,
+                Clock::get()?.unix_timestamp
+            ),
+            SaleError::SoftCapNotReached
+        );
+        Ok(())
+    }
+    /// Transfer context from the IDO token vault into the buyer's associated token account
+    pub fn into_transfer_from_ido_token_vault_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.ido_token_vault.to_account_info(),
+            to: self.user_ido_token_account.to_account_info(),
+            authority: self.ido_token_vault.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
     }
 }
 
 /// Validation struct for reading fields of both SaleAccount and AuthorizedSaleAccount
 #[derive(Accounts)]
 pub struct ReadAccounts<'info> {
+    #[account(has_one = sale_account)]
     pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
     pub sale_account: Account<'info, SaleAccount>,
-    pub user: Signer<'info>,
+}
+
+/// Validation struct for reading a page of the buyer registry
+#[derive(Accounts)]
+#[instruction(_page_index: u64)]
+pub struct ReadBuyerRegistryPage<'info> {
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        seeds = [
+            b"buyer-registry",
+            authorized_sale_account.key().as_ref(),
+            _page_index.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub buyer_registry_page: Account<'info, BuyerRegistryPage>,
+}
+
+/// Validation struct for reading buyer's info, which may not have been created yet
+#[derive(Accounts)]
+#[instruction(_buyer: Pubkey)]
+pub struct ReadBuyerInfoIfCreated<'info> {
+    /// CHECK: only read when it has been created, then deserialized as a BuyerInfo
+    #[account(
+        seeds = [
+            b"buyer-info",
+            authorized_sale_account.key().as_ref(),
+            _buyer.as_ref()
+        ],
+        bump
+    )]
+    pub buyer_info: UncheckedAccount<'info>,
+    pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
 }
 
 /// Validation struct for reading buyer's info and all data fields (AuthorizedSaleAccount + SaleAccount)
 #[derive(Accounts)]
 #[instruction(_buyer: Pubkey)]
 pub struct ReadBuyerInfoAndAccounts<'info> {
-    pub user: Signer<'info>,
-    #[account(seeds = [b"buyer-info", _buyer.as_ref()], bump = buyer_info.bump)]
+    #[account(
+        seeds = [
+            b"buyer-info",
+            authorized_sale_account.key().as_ref(),
+            _buyer.as_ref()
+        ],
+        bump = buyer_info.bump
+    )]
     pub buyer_info: Account<'info, BuyerInfo>,
+    #[account(
+        seeds = [b"sale-account", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.sale_account_bump
+    )]
     pub sale_account: Account<'info, SaleAccount>,
+    #[account(has_one = sale_account)]
     pub authorized_sale_account: Account<'info, AuthorizedSaleAccount>,
+    #[account(
+        seeds = [b"tranche-schedule", authorized_sale_account.key().as_ref()],
+        bump = authorized_sale_account.tranche_schedule_bump
+    )]
+    pub tranche_schedule: Account<'info, TrancheSchedule>,
 }
 
 // Accouunts
 #[account]
+#[derive(Debug, Default)]
 pub struct AuthorizedSaleAccount {
     // Pubkey of Admin
     pub admin: Pubkey, // 32 bytes
+    // Pubkey and bump of the SaleAccount PDA belonging to this sale
+    pub sale_account: Pubkey,  // 32 bytes
+    pub sale_account_bump: u8, // 1 bytes
+    // Pubkey allowed to write the buyers' allocations, besides the admin
+    pub allocation_authority: Pubkey, // 32 bytes
+    // Merkle root of the whitelist of (buyer, max allocation) pairs
+    pub allocation_merkle_root: [u8; 32], // 32 bytes
+    // Mint of the token buyers pay with, and its decimals
+    pub pay_token_mint: Pubkey, // 32 bytes
+    pub pay_token_decimals: u8, // 1 bytes
+    // Bump of the PDA token account holding the buyers' payments
+    pub pay_token_vault_bump: u8, // 1 bytes
+    // Mint of the token being sold, and its decimals
+    pub ido_token_mint: Pubkey, // 32 bytes
+    pub ido_token_decimals: u8, // 1 bytes
+    // Bump of the PDA token account holding the IDO tokens to be claimed
+    pub ido_token_vault_bump: u8, // 1 bytes
 
-    // Price of idoToken in payToken value based on ratio
-    pub ido_token_price_ratio: u64,      // 8 bytes
-    pub ido_token_price_multiplier: u64, // 8 bytes
+    // Price of idoToken in payToken value, as numerator / denominator payTokens per idoToken
+    pub ido_token_price: Price, // 16 bytes
     // Initial Allocation on Ido Tokens, if 0, then there is no initial allocation
     pub initial_percentage_allocation_ido_tokens: u8, // 1 bytes
 
     // Total amount of tokens to be sold, is Set at the funding
     pub tokens_for_sale: u128, // 16 bytes
+    // Least pay tokens to raise, else buyers get refunded, if 0, then there is no soft cap
+    pub soft_cap_pay_tokens: u128, // 16 bytes
+    // Most tokens that can be sold, is Set at the initialization
+    pub hard_cap_ido_tokens: u128, // 16 bytes
 
     // Start and End date for the eligible addresses to buy their tokens/funds
     pub start_date_funding: i64, // 8 bytes
@@ -778,6 +2072,10 @@ pub struct AuthorizedSaleAccount {
     // End date until when token can be claimed
     pub start_date_of_claiming_tokens: i64, // 8 bytes
     pub end_date_of_claiming_tokens: i64,   // 8 bytes
+    // Until when only the initial allocation can be claimed, if 0, then there is no cliff
+    pub cliff_date_of_claiming_tokens: i64, // 8 bytes
+    // Bump of the PDA holding the unlock table, used instead of the linear vesting when set
+    pub tranche_schedule_bump: u8, // 1 bytes
 
     // If the IDO token has been funded to the contract
     pub is_ido_token_funded_to_contract: bool, // 1 bytes
@@ -785,21 +2083,49 @@ pub struct AuthorizedSaleAccount {
     pub is_funding_canceled: bool, // 1 bytes
     // Enables the payment only to be in one transaction
     pub in_one_transaction: bool, // 1 bytes
+    // Requires the IDO tokens to be funded before they can be bought
+    pub is_fund_first: bool, // 1 bytes
     // Enable claiming
     pub is_claiming_open: bool, // 1 bytes
-} // 108 bytes
+    // If claiming has ever been enabled, closing it again does not undo the claims
+    pub has_claiming_opened: bool, // 1 bytes
+
+    // Window during which users can register for the lottery
+    pub start_date_lottery_registration: i64, // 8 bytes
+    pub end_date_lottery_registration: i64,   // 8 bytes
+    // Number of tickets that will win an allocation
+    pub number_of_lottery_winners: u64, // 8 bytes
+    // Hash of the secret the admin reveals before the draw
+    pub lottery_commitment: [u8; 32], // 32 bytes
+    // Seed the winners are derived from, set on the draw
+    pub lottery_seed: [u8; 32], // 32 bytes
+    // If the lottery has been drawn
+    pub is_lottery_played: bool, // 1 bytes
+    // Future slot, named on setup, whose hash seeds the draw
+    pub lottery_draw_slot: u64, // 8 bytes
+    // Secret revealed by the admin, zeros until then
+    pub lottery_secret: [u8; 32], // 32 bytes
+    // If the admin revealed the secret before the draw slot
+    pub is_lottery_secret_revealed: bool, // 1 bytes
+} // 439 bytes
 
 #[account]
 pub struct SaleAccount {
     // Spend Pay Count
     pub total_spend_pay_tokens: u128, // 16 bytes
+    // Withdrawn Pay Count
+    pub total_withdrawn_pay_tokens: u128, // 16 bytes
     // Sold tokens count
     pub total_allocated_ido_tokens: u128, // 16 bytes
+    // Withdrawn unsold tokens count
+    pub total_withdrawn_unsold_ido_tokens: u128, // 16 bytes
     // Investors count
     pub investor_count: u64, // 8 bytes
-    // Array to keep track of all the buyers
-    pub buyers_list: Vec<Pubkey>, // upto 100 buyers, (4 + 100 * 32) bytes = 3204 bytes
-} // 3244 bytes
+    // Lottery tickets count
+    pub lottery_ticket_count: u64, // 8 bytes
+    // Buyers added to the buyer registry count
+    pub buyer_registry_count: u64, // 8 bytes
+} // 88 bytes
 
 #[account]
 pub struct BuyerInfo {
@@ -811,8 +2137,186 @@ pub struct BuyerInfo {
     ido_tokens_claimed: u128, // 16 bytes
     // keep track if the buyer has claimed the pay tokens spend on IDO cancel
     has_claimed_pay_tokens: bool, // 1 bytes
-    bump: u8,                     // 1 bytes
-} // 50 bytes
+    // keep track if the buyer has been added to the buyer registry
+    is_in_buyer_registry: bool, // 1 bytes
+    // vesting of this buyer, instead of the one of the sale
+    vesting_override: Option<VestingOverride>, // 1 + 17 bytes
+    bump: u8,                                  // 1 bytes
+} // 69 bytes
+
+// Buyers per page of the buyer registry
+pub const BUYER_REGISTRY_PAGE_SIZE: usize = 100;
+
+#[account]
+pub struct BuyerRegistryPage {
+    // Page of the buyers, in order of registration
+    pub buyers: Vec<Pubkey>, // upto 100 buyers, (4 + 100 * 32) bytes = 3204 bytes
+} // 3204 bytes
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Price {
+    pub numerator: u64,   // 8 bytes
+    pub denominator: u64, // 8 bytes
+} // 16 bytes
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VestingOverride {
+    // Initial Allocation on Ido Tokens, if 0, then there is no initial allocation
+    pub initial_percentage: u8, // 1 bytes
+    // Seconds after the start date of claiming, until when only the initial allocation
+    // can be claimed, if 0, then there is no cliff
+    pub cliff_duration: i64, // 8 bytes
+    // Seconds after the start date of claiming, until everything can be claimed
+    pub duration: i64, // 8 bytes
+} // 17 bytes
+
+// Unlock points per unlock table, monthly over 4 years
+pub const MAX_TRANCHES: usize = 48;
+
+#[account]
+pub struct TrancheSchedule {
+    // Unlock table, empty for linear vesting
+    pub tranches: Vec<Tranche>, // upto 48 tranches, (4 + 48 * 10) bytes = 484 bytes
+} // 484 bytes
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tranche {
+    // When the share unlocks
+    pub timestamp: i64, // 8 bytes
+    // Share of the IDO tokens unlocked in total at this point, in basis points
+    pub cumulative_bps: u16, // 2 bytes
+} // 10 bytes
+
+#[account]
+pub struct Allocation {
+    // keep track of how many IDO tokens the buyer is allowed to buy
+    ido_tokens_allocation: u128, // 16 bytes
+    bump: u8,                    // 1 bytes
+} // 17 bytes
+
+#[account]
+pub struct LotteryTicket {
+    // ticket number, in order of registration
+    ticket_number: u64, // 8 bytes
+    // keep track if the ticket has been marked as winner
+    is_winner: bool, // 1 bytes
+    bump: u8,        // 1 bytes
+} // 10 bytes
+
+// Errors
+#[error_code]
+pub enum SaleError {
+    #[msg("The starting date of the funding should be before the end date of the funding")]
+    InvalidFundingDates,
+    #[msg("The Funding Period is not Open")]
+    FundingNotOpen,
+    #[msg("The Funding Period has not ended")]
+    FundingNotEnded,
+    #[msg("Funding has been canceled")]
+    FundingCanceled,
+    #[msg("Funding has not been canceled, nor has it failed to reach the soft cap")]
+    FundingNotCanceled,
+    #[msg("Claiming is already enabled")]
+    ClaimingAlreadyEnabled,
+    #[msg("Cannot claim, you need to wait until claiming is enabled")]
+    ClaimingNotOpen,
+    #[msg("You cannot give more than 100 percent of the token allocation")]
+    PercentageAboveHundred,
+    #[msg("Already funded tokens")]
+    AlreadyFunded,
+    #[msg("You should at least match the totalAllocatedIdoTokens")]
+    FundingBelowAllocatedIdoTokens,
+    #[msg("You do not have enough IDO tokens to fund the contract")]
+    InsufficientIdoTokens,
+    #[msg("Tokens have not been added to the contract YET")]
+    IdoTokensNotFunded,
+    #[msg("Amount has to be positive")]
+    AmountNotPositive,
+    #[msg("You do not have enough pay tokens")]
+    InsufficientPayTokens,
+    #[msg("You cannot buy more tokens than is allowed according to your lottery allocation calculation")]
+    AllocationExceeded,
+    #[msg("You need to buy the entire allocation in one transaction")]
+    EntireAllocationRequired,
+    #[msg("You are not a buyer")]
+    NotABuyer,
+    #[msg("You have already claimed the tokens")]
+    AlreadyClaimed,
+    #[msg("Nothing can be claimed yet")]
+    NothingToClaim,
+    #[msg("You have been refunded already")]
+    AlreadyRefunded,
+    #[msg("You cannot withdraw more pay tokens than the buyers have paid")]
+    WithdrawExceedsRaisedPayTokens,
+    #[msg("You cannot withdraw more IDO tokens than are unsold")]
+    WithdrawExceedsUnsoldIdoTokens,
+    #[msg("Only the admin or the allocation authority can set allocations")]
+    NotAllocationAuthority,
+    #[msg(
+        "The lottery registration should start before it ends, and end before the funding starts"
+    )]
+    InvalidLotteryDates,
+    #[msg("Users have already registered for the lottery")]
+    LotteryAlreadyStarted,
+    #[msg("The lottery registration is not open")]
+    LotteryRegistrationNotOpen,
+    #[msg("The lottery registration has not ended yet")]
+    LotteryRegistrationNotEnded,
+    #[msg("The lottery has already been played")]
+    LotteryAlreadyPlayed,
+    #[msg("The lottery has not been played yet")]
+    LotteryNotPlayed,
+    #[msg("The revealed secret does not match the lottery commitment")]
+    InvalidLotteryReveal,
+    #[msg("The ticket has not won the lottery")]
+    NotALotteryWinner,
+    #[msg("The ticket has already been marked as winner")]
+    AlreadyMarkedWinner,
+    #[msg("The merkle proof does not match this buyer and allocation")]
+    InvalidMerkleProof,
+    #[msg("The buyer registry page is not the one currently being filled")]
+    WrongBuyerRegistryPage,
+    #[msg("The buyer has already been added to the buyer registry")]
+    AlreadyInBuyerRegistry,
+    #[msg("The start date of claiming should be before its end date")]
+    InvalidClaimingDates,
+    #[msg("The cliff date should not be after the end date of claiming")]
+    InvalidCliffDate,
+    #[msg("The unlock table should have increasing timestamps and shares, ending at 100%")]
+    InvalidTrancheSchedule,
+    #[msg("The cliff of the vesting override should be between its start and its end")]
+    InvalidVestingOverride,
+    #[msg("The price should have a positive numerator and denominator")]
+    InvalidPrice,
+    #[msg("The Funding Period has already started")]
+    FundingAlreadyStarted,
+    #[msg("Once the funding has started, its end date can only be extended while it is open")]
+    FundingDatesLocked,
+    #[msg("The funding should end before the claiming starts and ends")]
+    FundingOverlapsClaiming,
+    #[msg("You cannot buy more tokens than the hard cap of the sale")]
+    HardCapExceeded,
+    #[msg("Funded first, you should at least match the hard cap")]
+    FundingBelowHardCap,
+    #[msg("The funding did not reach the soft cap, buyers can get refunded instead")]
+    SoftCapNotReached,
+    #[msg("The soft cap should not be above the price of the hard cap")]
+    SoftCapAboveHardCap,
+    #[msg("The sale cannot be canceled once pay tokens are withdrawn or claiming has opened")]
+    SaleAlreadySettled,
+    #[msg("The lottery draw slot should be in the future")]
+    InvalidLotteryDrawSlot,
+    #[msg("The lottery secret can only be revealed after the registration, before the draw slot")]
+    LotteryRevealClosed,
+    #[msg("The lottery draw slot has not passed yet")]
+    LotteryDrawSlotNotReached,
+    #[msg("The hash of the lottery draw slot is no longer available, a new one should be set")]
+    LotteryDrawSlotExpired,
+    #[msg("The lottery draw slot can only be moved once its hash is no longer available")]
+    LotteryDrawSlotNotExpired,
+    #[msg("Arithmetic overflow or underflow")]
+    MathOverflow,
+}
 
 // Events
 /// Event that will get emitted on buying IDO tokens
@@ -829,6 +2333,69 @@ pub struct ClaimedIDOTokens {
     pub buyer: Pubkey,
     pub ido_tokens_to_get: u128,
 }
+/// Event that will get emitted on refunding pay tokens of a canceled sale
+#[event]
+pub struct RefundedPayTokens {
+    pub buyer: Pubkey,
+    pub pay_tokens_refunded: u128,
+}
+/// Event that will get emitted on withdrawing the raised pay tokens
+#[event]
+pub struct WithdrewPayTokens {
+    pub admin: Pubkey,
+    pub treasury_pay_token_account: Pubkey,
+    pub pay_tokens_withdrawn: u128,
+}
+/// Event that will get emitted on withdrawing the unsold IDO tokens
+#[event]
+pub struct WithdrewUnsoldIdoTokens {
+    pub admin: Pubkey,
+    pub ido_tokens_withdrawn: u128,
+}
+/// Event that will get emitted on setting up the lottery
+#[event]
+pub struct SetUpLottery {
+    pub admin: Pubkey,
+    pub start_date_lottery_registration: i64,
+    pub end_date_lottery_registration: i64,
+    pub number_of_lottery_winners: u64,
+    pub lottery_commitment: [u8; 32],
+    pub lottery_draw_slot: u64,
+}
+/// Event that will get emitted on registering for the lottery
+#[event]
+pub struct RegisteredForLottery {
+    pub buyer: Pubkey,
+    pub ticket_number: u64,
+}
+/// Event that will get emitted on revealing the lottery secret
+#[event]
+pub struct RevealedLotterySecret {
+    pub admin: Pubkey,
+    pub lottery_secret: [u8; 32],
+}
+/// Event that will get emitted on drawing the lottery
+#[event]
+pub struct DrewLottery {
+    pub lottery_seed: [u8; 32],
+    pub lottery_ticket_count: u64,
+    pub number_of_lottery_winners: u64,
+    pub is_lottery_secret_revealed: bool,
+}
+/// Event that will get emitted on changing the lottery draw slot
+#[event]
+pub struct ChangedLotteryDrawSlot {
+    pub admin: Pubkey,
+    pub old_lottery_draw_slot: u64,
+    pub lottery_draw_slot: u64,
+}
+/// Event that will get emitted on marking a lottery winner
+#[event]
+pub struct MarkedLotteryWinner {
+    pub buyer: Pubkey,
+    pub ticket_number: u64,
+    pub ido_tokens_allocation: u128,
+}
 
 // Logging
 /// Event that will get emmited on changing end date, untill token can be claimed
@@ -838,6 +2405,27 @@ pub struct ChangedEndDateOfClaimingTokens {
     pub old_end_date_of_claiming_tokens: i64,
     pub end_date_of_claiming_tokens: i64,
 }
+/// Event that will get emmited on changing the cliff date of claiming tokens
+#[event]
+pub struct ChangedCliffDateOfClaimingTokens {
+    pub admin: Pubkey,
+    pub old_cliff_date_of_claiming_tokens: i64,
+    pub cliff_date_of_claiming_tokens: i64,
+}
+/// Event that will get emmited on changing the unlock table
+#[event]
+pub struct ChangedTrancheSchedule {
+    pub admin: Pubkey,
+    pub tranches: Vec<Tranche>,
+}
+/// Event that will get emmited on changing the vesting override of a buyer
+#[event]
+pub struct ChangedBuyerVestingOverride {
+    pub admin: Pubkey,
+    pub buyer: Pubkey,
+    pub old_vesting_override: Option<VestingOverride>,
+    pub vesting_override: Option<VestingOverride>,
+}
 /// Event that will get emmited on changing end date, untill token can be claimed
 #[event]
 pub struct ChangedIsClaimingOpen {
@@ -845,6 +2433,51 @@ pub struct ChangedIsClaimingOpen {
     pub old_is_claiming_open: bool,
     pub is_claiming_open: bool,
 }
+/// Event that will get emmited on changing the allocation authority
+#[event]
+pub struct ChangedAllocationAuthority {
+    pub admin: Pubkey,
+    pub old_allocation_authority: Pubkey,
+    pub allocation_authority: Pubkey,
+}
+/// Event that will get emmited on changing the whitelist merkle root
+#[event]
+pub struct ChangedAllocationMerkleRoot {
+    pub admin: Pubkey,
+    pub old_allocation_merkle_root: [u8; 32],
+    pub allocation_merkle_root: [u8; 32],
+}
+/// Event that will get emmited on changing the allocation of a buyer
+#[event]
+pub struct ChangedAllocation {
+    pub authority: Pubkey,
+    pub buyer: Pubkey,
+    pub old_ido_tokens_allocation: u128,
+    pub ido_tokens_allocation: u128,
+}
+/// Event that will get emmited on changing the soft cap of the funding
+#[event]
+pub struct ChangedSoftCapPayTokens {
+    pub admin: Pubkey,
+    pub old_soft_cap_pay_tokens: u128,
+    pub soft_cap_pay_tokens: u128,
+}
+/// Event that will get emmited on changing the funding dates
+#[event]
+pub struct ChangedFundingDates {
+    pub admin: Pubkey,
+    pub old_start_date_funding: i64,
+    pub old_end_date_funding: i64,
+    pub start_date_funding: i64,
+    pub end_date_funding: i64,
+}
+/// Event that will get emmited on changing the price of the IDO token
+#[event]
+pub struct ChangedIdoTokenPrice {
+    pub admin: Pubkey,
+    pub old_ido_token_price: Price,
+    pub ido_token_price: Price,
+}
 /// Event that will get emmited on changing initial percentage allocation of IDO tokens
 #[event]
 pub struct ChangedInitialPercentageAllocationIdoTokens {
@@ -0,0 +1,277 @@
+use crate::{AuthorizedSaleAccount, Price, SaleError};
+use anchor_lang::prelude::*;
+
+/// Which way a conversion between pay and IDO tokens rounds. Conversions round down
+/// what leaves the sale (IDO tokens out) and up what enters it (pay tokens in), so the
+/// dust always stays with the sale
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+impl Price {
+    /// A price should be positive, both ways
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.numerator > 0 && self.denominator > 0,
+            SaleError::InvalidPrice
+        );
+        Ok(())
+    }
+}
+
+/// `value * numerator / denominator`, rounded as asked
+pub fn mul_div(
+    value: u128,
+    numerator: u128,
+    denominator: u128,
+    rounding: Rounding,
+) -> Result<u128> {
+    let product = value
+        .checked_mul(numerator)
+        .ok_or(SaleError::MathOverflow)?;
+    let quotient = product
+        .checked_div(denominator)
+        .ok_or(SaleError::MathOverflow)?;
+    if rounding == Rounding::Up && product % denominator != 0 {
+        return Ok(quotient.checked_add(1).ok_or(SaleError::MathOverflow)?);
+    }
+    Ok(quotient)
+}
+
+impl AuthorizedSaleAccount {
+    /// Pay tokens worth `ido_tokens`, both in base units
+    pub fn pay_tokens_for(&self, ido_tokens: u128, rounding: Rounding) -> Result<u128> {
+        let (numerator, denominator) = self.base_unit_price()?;
+        mul_div(ido_tokens, numerator, denominator, rounding)
+    }
+
+    /// IDO tokens worth `pay_tokens`, both in base units
+    pub fn ido_tokens_for(&self, pay_tokens: u128, rounding: Rounding) -> Result<u128> {
+        let (numerator, denominator) = self.base_unit_price()?;
+        mul_div(pay_tokens, denominator, numerator, rounding)
+    }
+
+    /// IDO tokens bought with `pay_tokens` out of what is left of an allocation, which
+    /// costs its rounded up price. Paying that whole price buys exactly the rest, so an
+    /// allocation can always be bought out, but never exceeded
+    pub fn ido_tokens_bought_within(
+        &self,
+        pay_tokens: u128,
+        remaining_allocation: u128,
+    ) -> Result<u128> {
+        require!(
+            pay_tokens <= self.pay_tokens_for(remaining_allocation, Rounding::Up)?,
+            SaleError::AllocationExceeded
+        );
+        Ok(self
+            .ido_tokens_for(pay_tokens, Rounding::Down)?
+            .min(remaining_allocation))
+    }
+
+    /// The price of one IDO token base unit in pay token base units, as a fraction. Only
+    /// the difference of the decimals is scaled in, to keep the products small
+    fn base_unit_price(&self) -> Result<(u128, u128)> {
+        let numerator = self.ido_token_price.numerator as u128;
+        let denominator = self.ido_token_price.denominator as u128;
+        let scale = |exponent: u8| -> Result<u128> {
+            Ok(10u128
+                .checked_pow(exponent as u32)
+                .ok_or(SaleError::MathOverflow)?)
+        };
+        if self.pay_token_decimals >= self.ido_token_decimals {
+            let scale = scale(self.pay_token_decimals - self.ido_token_decimals)?;
+            Ok((
+                numerator
+                    .checked_mul(scale)
+                    .ok_or(SaleError::MathOverflow)?,
+                denominator,
+            ))
+        } else {
+            let scale = scale(self.ido_token_decimals - self.pay_token_decimals)?;
+            Ok((
+                numerator,
+                denominator
+                    .checked_mul(scale)
+                    .ok_or(SaleError::MathOverflow)?,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn priced_sale(
+        numerator: u64,
+        denominator: u64,
+        pay_decimals: u8,
+        ido_decimals: u8,
+    ) -> AuthorizedSaleAccount {
+        AuthorizedSaleAccount {
+            ido_token_price: Price {
+                numerator,
+                denominator,
+            },
+            pay_token_decimals: pay_decimals,
+            ido_token_decimals: ido_decimals,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prices_with_the_decimals_of_both_mints() {
+        // 0.4 pay tokens of 6 decimals for one IDO token of 9 decimals
+        let sale = priced_sale(2, 5, 6, 9);
+        assert_eq!(
+            sale.pay_tokens_for(1_000_000_000, Rounding::Down).unwrap(),
+            400_000
+        );
+        assert_eq!(
+            sale.ido_tokens_for(400_000, Rounding::Down).unwrap(),
+            1_000_000_000
+        );
+        // and the other way around
+        let sale = priced_sale(2, 5, 9, 6);
+        assert_eq!(
+            sale.pay_tokens_for(1_000_000, Rounding::Down).unwrap(),
+            400_000_000
+        );
+    }
+
+    #[test]
+    fn rounds_in_the_asked_direction() {
+        // one IDO base unit costs 0.0004 pay base units
+        let sale = priced_sale(2, 5, 6, 9);
+        assert_eq!(
+            sale.pay_tokens_for(22_345_623_767, Rounding::Down).unwrap(),
+            8_938_249
+        );
+        assert_eq!(
+            sale.pay_tokens_for(22_345_623_767, Rounding::Up).unwrap(),
+            8_938_250
+        );
+        assert_eq!(sale.pay_tokens_for(2_500, Rounding::Up).unwrap(), 1);
+        assert_eq!(sale.pay_tokens_for(0, Rounding::Up).unwrap(), 0);
+    }
+
+    #[test]
+    fn dust_is_not_lost() {
+        // 1 pay base unit buys 2500 IDO base units, where a fixed multiplier would give 0
+        let sale = priced_sale(2, 5, 6, 9);
+        assert_eq!(sale.ido_tokens_for(1, Rounding::Down).unwrap(), 2_500);
+    }
+
+    #[test]
+    fn the_rounded_up_price_buys_out_the_allocation() {
+        // 2500 IDO base units per pay base unit, so 1 pay base unit would buy 2500
+        let sale = priced_sale(2, 5, 6, 9);
+        assert_eq!(sale.ido_tokens_bought_within(1, 1_000).unwrap(), 1_000);
+        assert!(sale.ido_tokens_bought_within(2, 1_000).is_err());
+        assert!(sale.ido_tokens_bought_within(1, 0).is_err());
+    }
+
+    #[test]
+    fn invalid_prices_are_rejected() {
+        assert!(Price {
+            numerator: 0,
+            denominator: 5
+        }
+        .validate()
+        .is_err());
+        assert!(Price {
+            numerator: 2,
+            denominator: 0
+        }
+        .validate()
+        .is_err());
+        assert!(Price {
+            numerator: 2,
+            denominator: 5
+        }
+        .validate()
+        .is_ok());
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let sale = priced_sale(u64::MAX, 1, 38, 0);
+        assert!(sale.pay_tokens_for(u128::MAX, Rounding::Up).is_err());
+        assert!(priced_sale(1, 1, 39, 0)
+            .pay_tokens_for(1, Rounding::Down)
+            .is_err());
+    }
+
+    fn any_sale() -> impl Strategy<Value = AuthorizedSaleAccount> {
+        (1..=1_000_000u64, 1..=1_000_000u64, 0..=9u8, 0..=9u8).prop_map(
+            |(numerator, denominator, pay_decimals, ido_decimals)| {
+                priced_sale(numerator, denominator, pay_decimals, ido_decimals)
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn tokens_out_are_never_worth_more_than_the_payment(
+            sale in any_sale(),
+            pay_tokens in 0..=u64::MAX as u128,
+        ) {
+            let ido_tokens = sale.ido_tokens_for(pay_tokens, Rounding::Down).unwrap();
+            prop_assert!(sale.pay_tokens_for(ido_tokens, Rounding::Up).unwrap() <= pay_tokens);
+        }
+
+        #[test]
+        fn paying_the_rounded_up_price_buys_at_least_the_tokens(
+            sale in any_sale(),
+            ido_tokens in 0..=u64::MAX as u128,
+        ) {
+            let pay_tokens = sale.pay_tokens_for(ido_tokens, Rounding::Up).unwrap();
+            prop_assert!(sale.ido_tokens_for(pay_tokens, Rounding::Down).unwrap() >= ido_tokens);
+            prop_assert!(sale.pay_tokens_for(ido_tokens, Rounding::Down).unwrap() <= pay_tokens);
+        }
+
+        #[test]
+        fn one_pay_token_less_never_buys_the_tokens(
+            sale in any_sale(),
+            ido_tokens in 1..=u64::MAX as u128,
+        ) {
+            let pay_tokens = sale.pay_tokens_for(ido_tokens, Rounding::Up).unwrap();
+            prop_assert!(sale.ido_tokens_for(pay_tokens - 1, Rounding::Down).unwrap() < ido_tokens);
+        }
+
+        #[test]
+        fn buying_in_parts_never_exceeds_buying_at_once(
+            sale in any_sale(),
+            first in 0..=u64::MAX as u128,
+            second in 0..=u64::MAX as u128,
+        ) {
+            let in_parts = sale.ido_tokens_for(first, Rounding::Down).unwrap()
+                + sale.ido_tokens_for(second, Rounding::Down).unwrap();
+            prop_assert!(in_parts <= sale.ido_tokens_for(first + second, Rounding::Down).unwrap());
+        }
+
+        #[test]
+        fn buyers_are_never_over_allocated(
+            sale in any_sale(),
+            allocation in 0..=u64::MAX as u128,
+            payments in prop::collection::vec(0..=u64::MAX as u128, 1..10),
+        ) {
+            let mut ido_tokens_to_get = 0;
+            let mut spend_pay_tokens = 0;
+            for pay_tokens in payments {
+                if let Ok(ido_tokens) =
+                    sale.ido_tokens_bought_within(pay_tokens, allocation - ido_tokens_to_get)
+                {
+                    ido_tokens_to_get += ido_tokens;
+                    spend_pay_tokens += pay_tokens;
+                }
+            }
+            prop_assert!(ido_tokens_to_get <= allocation);
+            // and the tokens bought were paid for in full
+            prop_assert!(sale.pay_tokens_for(ido_tokens_to_get, Rounding::Down).unwrap() <= spend_pay_tokens);
+        }
+    }
+}
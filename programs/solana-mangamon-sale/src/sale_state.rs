@@ -0,0 +1,28 @@
+use crate::AuthorizedSaleAccount;
+
+impl AuthorizedSaleAccount {
+    /// Whether the funding has ended below the soft cap, so the buyers get refunded
+    /// instead of getting their IDO tokens
+    pub fn is_funding_failed(&self, total_spend_pay_tokens: u128, now_ts: i64) -> bool {
+        now_ts > self.end_date_funding && total_spend_pay_tokens < self.soft_cap_pay_tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn funding_fails_below_the_soft_cap_once_ended() {
+        let sale = AuthorizedSaleAccount {
+            end_date_funding: 1_000,
+            soft_cap_pay_tokens: 4_000,
+            ..Default::default()
+        };
+        assert!(!sale.is_funding_failed(0, 1_000));
+        assert!(sale.is_funding_failed(3_999, 1_001));
+        assert!(!sale.is_funding_failed(4_000, 1_001));
+        // without a soft cap the funding never fails
+        assert!(!AuthorizedSaleAccount::default().is_funding_failed(0, 1_001));
+    }
+}
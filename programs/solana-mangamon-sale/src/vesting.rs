@@ -0,0 +1,400 @@
+use crate::{
+    AuthorizedSaleAccount, BuyerInfo, SaleError, Tranche, TrancheSchedule, VestingOverride,
+};
+use anchor_lang::prelude::*;
+
+/// Basis points in 100%
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+/// How the IDO tokens bought are released, either linearly or by an unlock table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VestingSchedule {
+    Linear(LinearVesting),
+    Tranches(Vec<Tranche>),
+}
+
+/// An initial percentage unlocked right away, and the rest linearly between the start
+/// and the end date of claiming. Until the optional cliff date, only the initial
+/// percentage is released
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinearVesting {
+    pub start_date: i64,
+    pub end_date: i64,
+    pub initial_percentage: u8,
+    // 0 if there is no cliff
+    pub cliff_date: i64,
+}
+
+impl VestingSchedule {
+    /// Tokens vested at `now` out of `total`
+    pub fn vested(&self, now: i64, total: u128) -> Result<u128> {
+        match self {
+            VestingSchedule::Linear(linear) => linear.vested(now, total),
+            VestingSchedule::Tranches(tranches) => vested_by_tranches(tranches, now, total),
+        }
+    }
+
+    /// Tokens the buyer can claim at `now`, that is vested minus already claimed
+    pub fn releasable(&self, now: i64, buyer: &BuyerInfo) -> Result<u128> {
+        Ok(self
+            .vested(now, buyer.ido_tokens_to_get)?
+            .saturating_sub(buyer.ido_tokens_claimed))
+    }
+}
+
+impl LinearVesting {
+    /// Every product is taken before its division, and divisions round down, so the
+    /// vested amount never exceeds what the schedule allows and only reaches `total` at
+    /// the end date. Nothing is vested before the start date
+    pub fn vested(&self, now: i64, total: u128) -> Result<u128> {
+        if now < self.start_date {
+            return Ok(0);
+        }
+        if now >= self.end_date {
+            return Ok(total);
+        }
+        let initial = total
+            .checked_mul(self.initial_percentage as u128)
+            .ok_or(SaleError::MathOverflow)?
+            / 100;
+        if now < self.cliff_date {
+            return Ok(initial);
+        }
+        // start_date <= now < end_date, so both differences are positive
+        let seconds_passed = (now - self.start_date) as u128;
+        let seconds_in_total = (self.end_date - self.start_date) as u128;
+        let linear = total
+            .checked_sub(initial)
+            .ok_or(SaleError::MathOverflow)?
+            .checked_mul(seconds_passed)
+            .ok_or(SaleError::MathOverflow)?
+            / seconds_in_total;
+        Ok(initial.checked_add(linear).ok_or(SaleError::MathOverflow)?)
+    }
+}
+
+/// The share of the last unlock point reached, rounded down
+fn vested_by_tranches(tranches: &[Tranche], now: i64, total: u128) -> Result<u128> {
+    let cumulative_bps = tranches
+        .iter()
+        .take_while(|tranche| tranche.timestamp <= now)
+        .last()
+        .map_or(0, |tranche| tranche.cumulative_bps);
+    Ok(total
+        .checked_mul(cumulative_bps as u128)
+        .ok_or(SaleError::MathOverflow)?
+        / BPS_DENOMINATOR)
+}
+
+/// Check an unlock table: strictly increasing timestamps, non-decreasing shares, and
+/// everything unlocked at the last point. An empty table means linear vesting
+pub fn validate_tranches(tranches: &[Tranche]) -> Result<()> {
+    for pair in tranches.windows(2) {
+        require!(
+            pair[0].timestamp < pair[1].timestamp
+                && pair[0].cumulative_bps <= pair[1].cumulative_bps,
+            SaleError::InvalidTrancheSchedule
+        );
+    }
+    if let Some(last) = tranches.last() {
+        require!(
+            last.cumulative_bps as u128 == BPS_DENOMINATOR,
+            SaleError::InvalidTrancheSchedule
+        );
+    }
+    Ok(())
+}
+
+impl AuthorizedSaleAccount {
+    /// The vesting schedule of a buyer: his own override when the admin assigned one,
+    /// else the one shared by every buyer of the sale, where the unlock table takes over
+    /// the linear schedule when it is set
+    pub fn vesting_schedule(
+        &self,
+        tranche_schedule: &TrancheSchedule,
+        buyer: &BuyerInfo,
+    ) -> VestingSchedule {
+        if let Some(vesting_override) = buyer.vesting_override {
+            VestingSchedule::Linear(self.overridden_vesting(&vesting_override))
+        } else if tranche_schedule.tranches.is_empty() {
+            VestingSchedule::Linear(LinearVesting {
+                start_date: self.start_date_of_claiming_tokens,
+                end_date: self.end_date_of_claiming_tokens,
+                initial_percentage: self.initial_percentage_allocation_ido_tokens,
+                cliff_date: self.cliff_date_of_claiming_tokens,
+            })
+        } else {
+            VestingSchedule::Tranches(tranche_schedule.tranches.clone())
+        }
+    }
+
+    /// A buyer's override, counted from the start date of claiming
+    fn overridden_vesting(&self, vesting_override: &VestingOverride) -> LinearVesting {
+        let start_date = self.start_date_of_claiming_tokens;
+        LinearVesting {
+            start_date,
+            end_date: start_date.saturating_add(vesting_override.duration),
+            initial_percentage: vesting_override.initial_percentage,
+            cliff_date: if vesting_override.cliff_duration > 0 {
+                start_date.saturating_add(vesting_override.cliff_duration)
+            } else {
+                0
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: i64 = 1_000;
+    const END: i64 = 2_000;
+
+    fn linear(initial_percentage: u8) -> LinearVesting {
+        LinearVesting {
+            start_date: START,
+            end_date: END,
+            initial_percentage,
+            cliff_date: 0,
+        }
+    }
+
+    fn tranche(timestamp: i64, cumulative_bps: u16) -> Tranche {
+        Tranche {
+            timestamp,
+            cumulative_bps,
+        }
+    }
+
+    fn buyer(ido_tokens_to_get: u128, ido_tokens_claimed: u128) -> BuyerInfo {
+        BuyerInfo {
+            spend_pay_tokens: 0,
+            ido_tokens_to_get,
+            ido_tokens_claimed,
+            has_claimed_pay_tokens: false,
+            is_in_buyer_registry: false,
+            vesting_override: None,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn initial_unlock_multiplies_before_dividing() {
+        // 999 / 100 * 33 would give 297
+        assert_eq!(linear(33).vested(START, 999).unwrap(), 329);
+    }
+
+    #[test]
+    fn nothing_is_vested_before_start() {
+        assert_eq!(linear(20).vested(START - 1, 1_000).unwrap(), 0);
+        assert_eq!(linear(0).vested(i64::MIN, 1_000).unwrap(), 0);
+        assert_eq!(linear(20).vested(START, 1_000).unwrap(), 200);
+    }
+
+    #[test]
+    fn linear_release_multiplies_before_dividing() {
+        // 7 tokens over 1000 seconds: a per second rate would round down to 0
+        assert_eq!(linear(0).vested(START + 500, 7).unwrap(), 3);
+        assert_eq!(linear(0).vested(START + 999, 7).unwrap(), 6);
+    }
+
+    #[test]
+    fn linear_release_starts_after_the_initial_unlock() {
+        // 200 unlocked, then 800 over 1000 seconds
+        assert_eq!(linear(20).vested(START + 250, 1_000).unwrap(), 400);
+    }
+
+    #[test]
+    fn everything_is_vested_at_the_end_date() {
+        assert_eq!(linear(33).vested(END, 999).unwrap(), 999);
+        assert_eq!(linear(0).vested(END + 1, 7).unwrap(), 7);
+    }
+
+    #[test]
+    fn vested_never_decreases() {
+        let mut last = 0;
+        for now in (START - 10)..(END + 10) {
+            let vested = linear(15).vested(now, 123_457).unwrap();
+            assert!(vested >= last);
+            last = vested;
+        }
+        assert_eq!(last, 123_457);
+    }
+
+    #[test]
+    fn empty_claiming_period_vests_everything_at_once() {
+        let schedule = LinearVesting {
+            end_date: START,
+            ..linear(10)
+        };
+        assert_eq!(schedule.vested(START - 1, 1_000).unwrap(), 0);
+        assert_eq!(schedule.vested(START, 1_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn end_before_start_does_not_underflow() {
+        let schedule = LinearVesting {
+            end_date: START - 500,
+            ..linear(10)
+        };
+        assert_eq!(schedule.vested(START - 250, 1_000).unwrap(), 0);
+        assert_eq!(schedule.vested(START, 1_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn only_the_initial_unlock_is_released_before_the_cliff() {
+        let schedule = LinearVesting {
+            cliff_date: START + 500,
+            ..linear(20)
+        };
+        assert_eq!(schedule.vested(START + 499, 1_000).unwrap(), 200);
+        // At the cliff the linear release catches up from the start date
+        assert_eq!(schedule.vested(START + 500, 1_000).unwrap(), 600);
+        assert_eq!(schedule.vested(END, 1_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn cliff_before_start_has_no_effect() {
+        let with_cliff = LinearVesting {
+            cliff_date: START - 1,
+            ..linear(20)
+        };
+        assert_eq!(
+            with_cliff.vested(START + 250, 1_000).unwrap(),
+            linear(20).vested(START + 250, 1_000).unwrap()
+        );
+    }
+
+    #[test]
+    fn tranches_release_the_last_unlock_point_reached() {
+        let schedule = VestingSchedule::Tranches(vec![
+            tranche(START, 2_500),
+            tranche(START + 100, 5_000),
+            tranche(START + 200, 10_000),
+        ]);
+        assert_eq!(schedule.vested(START - 1, 1_000).unwrap(), 0);
+        assert_eq!(schedule.vested(START, 1_000).unwrap(), 250);
+        assert_eq!(schedule.vested(START + 199, 1_000).unwrap(), 500);
+        assert_eq!(schedule.vested(START + 200, 1_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn tranches_multiply_before_dividing() {
+        // 999 / 10000 * 3333 would give 0
+        let schedule = VestingSchedule::Tranches(vec![tranche(START, 3_333), tranche(END, 10_000)]);
+        assert_eq!(schedule.vested(START, 999).unwrap(), 332);
+        assert_eq!(schedule.vested(END, 999).unwrap(), 999);
+    }
+
+    #[test]
+    fn tranche_tables_are_validated() {
+        assert!(validate_tranches(&[]).is_ok());
+        assert!(validate_tranches(&[tranche(START, 5_000), tranche(END, 10_000)]).is_ok());
+        // not everything unlocked
+        assert!(validate_tranches(&[tranche(START, 5_000)]).is_err());
+        // above 100%
+        assert!(validate_tranches(&[tranche(START, 10_001)]).is_err());
+        // timestamps not increasing
+        assert!(validate_tranches(&[tranche(END, 5_000), tranche(END, 10_000)]).is_err());
+        // shares decreasing
+        assert!(validate_tranches(&[
+            tranche(START, 6_000),
+            tranche(START + 1, 5_000),
+            tranche(END, 10_000)
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn buyer_override_takes_over_the_sale_schedule() {
+        let authorized_sale_account = AuthorizedSaleAccount {
+            start_date_of_claiming_tokens: START,
+            end_date_of_claiming_tokens: END,
+            initial_percentage_allocation_ido_tokens: 20,
+            ..Default::default()
+        };
+        let tranche_schedule = TrancheSchedule {
+            tranches: vec![tranche(START, 10_000)],
+        };
+        let mut private_round_buyer = buyer(1_000, 0);
+        private_round_buyer.vesting_override = Some(VestingOverride {
+            initial_percentage: 0,
+            cliff_duration: 500,
+            duration: 2_000,
+        });
+
+        assert_eq!(
+            authorized_sale_account.vesting_schedule(&tranche_schedule, &buyer(1_000, 0)),
+            VestingSchedule::Tranches(tranche_schedule.tranches.clone())
+        );
+        assert_eq!(
+            authorized_sale_account.vesting_schedule(&tranche_schedule, &private_round_buyer),
+            VestingSchedule::Linear(LinearVesting {
+                start_date: START,
+                end_date: START + 2_000,
+                initial_percentage: 0,
+                cliff_date: START + 500,
+            })
+        );
+        let schedule =
+            authorized_sale_account.vesting_schedule(&tranche_schedule, &private_round_buyer);
+        assert_eq!(
+            schedule
+                .releasable(START + 499, &private_round_buyer)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            schedule
+                .releasable(START + 500, &private_round_buyer)
+                .unwrap(),
+            250
+        );
+        assert_eq!(
+            schedule
+                .releasable(START + 2_000, &private_round_buyer)
+                .unwrap(),
+            1_000
+        );
+    }
+
+    #[test]
+    fn buyer_override_without_cliff() {
+        let authorized_sale_account = AuthorizedSaleAccount {
+            start_date_of_claiming_tokens: START,
+            ..Default::default()
+        };
+        let vesting_override = VestingOverride {
+            initial_percentage: 10,
+            cliff_duration: 0,
+            duration: 1_000,
+        };
+        assert_eq!(
+            authorized_sale_account.overridden_vesting(&vesting_override),
+            LinearVesting {
+                start_date: START,
+                end_date: START + 1_000,
+                initial_percentage: 10,
+                cliff_date: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn releasable_subtracts_what_was_claimed() {
+        let schedule = VestingSchedule::Linear(linear(20));
+        assert_eq!(
+            schedule
+                .releasable(START + 250, &buyer(1_000, 300))
+                .unwrap(),
+            100
+        );
+        assert_eq!(schedule.releasable(END, &buyer(1_000, 1_000)).unwrap(), 0);
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        assert!(linear(50).vested(START, u128::MAX).is_err());
+    }
+}
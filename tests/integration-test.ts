@@ -3,29 +3,185 @@ import { Program } from "@project-serum/anchor";
 import { PublicKey } from '@solana/web3.js';
 import { SolanaMangamonSale } from "../target/types/solana_mangamon_sale";
 import { expect } from 'chai';
+import { createHash } from 'crypto';
 
 // Configure the client to use the local cluster.
 const provider = anchor.AnchorProvider.env();
 anchor.setProvider(provider);
 
 const program = anchor.workspace.SolanaMangamonSale as Program<SolanaMangamonSale>;
+const tokenProgram = anchor.Spl.token(provider);
 
-async function initializateAccount(authorizedSaleAccount: anchor.web3.Keypair, saleAccount: anchor.web3.Keypair) {
+async function createMint(decimals: number) {
+    const mint = anchor.web3.Keypair.generate();
+    await tokenProgram.methods
+        .initializeMint(decimals, provider.wallet.publicKey, null)
+        .accounts({
+            mint: mint.publicKey,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([mint])
+        .preInstructions([await tokenProgram.account.mint.createInstruction(mint)])
+        .rpc();
+    return mint.publicKey;
+}
+
+async function createTokenAccount(mint: PublicKey, owner: PublicKey, amount: anchor.BN) {
+    const tokenAccount = anchor.web3.Keypair.generate();
+    await tokenProgram.methods
+        .initializeAccount()
+        .accounts({
+            account: tokenAccount.publicKey,
+            mint: mint,
+            authority: owner,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .signers([tokenAccount])
+        .preInstructions([await tokenProgram.account.token.createInstruction(tokenAccount)])
+        .rpc();
+    await tokenProgram.methods
+        .mintTo(amount)
+        .accounts({
+            mint: mint,
+            to: tokenAccount.publicKey,
+            authority: provider.wallet.publicKey,
+        })
+        .rpc();
+    return tokenAccount.publicKey;
+}
+
+async function findSaleAccount(authorizedSaleAccount: PublicKey) {
+    const [saleAccount, _] = await PublicKey.findProgramAddress(
+        [
+            anchor.utils.bytes.utf8.encode("sale-account"),
+            authorizedSaleAccount.toBuffer()
+        ],
+        program.programId
+    );
+    return saleAccount;
+}
+
+async function findTrancheSchedule(authorizedSaleAccount: PublicKey) {
+    const [trancheSchedule, _] = await PublicKey.findProgramAddress(
+        [
+            anchor.utils.bytes.utf8.encode("tranche-schedule"),
+            authorizedSaleAccount.toBuffer()
+        ],
+        program.programId
+    );
+    return trancheSchedule;
+}
+
+async function findPayTokenVault(authorizedSaleAccount: PublicKey) {
+    const [payTokenVault, _] = await PublicKey.findProgramAddress(
+        [
+            anchor.utils.bytes.utf8.encode("pay-token-vault"),
+            authorizedSaleAccount.toBuffer()
+        ],
+        program.programId
+    );
+    return payTokenVault;
+}
+
+async function findIdoTokenVault(authorizedSaleAccount: PublicKey) {
+    const [idoTokenVault, _] = await PublicKey.findProgramAddress(
+        [
+            anchor.utils.bytes.utf8.encode("ido-token-vault"),
+            authorizedSaleAccount.toBuffer()
+        ],
+        program.programId
+    );
+    return idoTokenVault;
+}
+
+async function findAllocation(authorizedSaleAccount: PublicKey, buyer: PublicKey) {
+    const [allocation, _] = await PublicKey.findProgramAddress(
+        [
+            anchor.utils.bytes.utf8.encode("allocation"),
+            authorizedSaleAccount.toBuffer(),
+            buyer.toBuffer()
+        ],
+        program.programId
+    );
+    return allocation;
+}
+
+async function findLotteryTicket(authorizedSaleAccount: PublicKey, buyer: PublicKey) {
+    const [lotteryTicket, _] = await PublicKey.findProgramAddress(
+        [
+            anchor.utils.bytes.utf8.encode("lottery-ticket"),
+            authorizedSaleAccount.toBuffer(),
+            buyer.toBuffer()
+        ],
+        program.programId
+    );
+    return lotteryTicket;
+}
+
+async function chainTime() {
+    return await provider.connection.getBlockTime(await provider.connection.getSlot());
+}
+
+async function waitUntilAfter(timestamp: number) {
+    while (await chainTime() <= timestamp) {
+        await new Promise((resolve) => setTimeout(resolve, 1000));
+    }
+}
+
+async function waitUntilSlotAfter(slot: number) {
+    while (await provider.connection.getSlot() <= slot) {
+        await new Promise((resolve) => setTimeout(resolve, 400));
+    }
+}
+
+async function findBuyerRegistryPage(authorizedSaleAccount: PublicKey, pageIndex: anchor.BN) {
+    const [buyerRegistryPage, _] = await PublicKey.findProgramAddress(
+        [
+            anchor.utils.bytes.utf8.encode("buyer-registry"),
+            authorizedSaleAccount.toBuffer(),
+            pageIndex.toArrayLike(Buffer, "le", 8)
+        ],
+        program.programId
+    );
+    return buyerRegistryPage;
+}
+
+function whitelistLeaf(buyer: PublicKey, maxAllocation: anchor.BN) {
+    return createHash("sha256")
+        .update(Buffer.concat([
+            Buffer.from([0]),
+            buyer.toBuffer(),
+            maxAllocation.toArrayLike(Buffer, "le", 16)
+        ]))
+        .digest();
+}
+
+async function initializateAccount(authorizedSaleAccount: anchor.web3.Keypair) {
+    const saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+    const payTokenMint = await createMint(6);
+    const idoTokenMint = await createMint(9);
     await program.methods
         .initialize(
-            new anchor.BN(4000),
+            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
             new anchor.BN(1652972400),
             new anchor.BN(1654285600),
             new anchor.BN(1666504800),
             20,
             false,
+            new anchor.BN("1000000000000000"),
+            false,
         )
         .accounts({
             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-            saleAccount: saleAccount.publicKey,
+            saleAccount: saleAccount,
+            payTokenMint: payTokenMint,
+            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+            idoTokenMint: idoTokenMint,
+            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
             user: provider.wallet.publicKey,
         })
-        .signers([authorizedSaleAccount, saleAccount])
+        .signers([authorizedSaleAccount])
         .rpc();
 
     await program.methods
@@ -34,26 +190,26 @@ async function initializateAccount(authorizedSaleAccount: anchor.web3.Keypair, s
         )
         .accounts({
             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-            saleAccount: saleAccount.publicKey,
+            saleAccount: saleAccount,
             admin: provider.wallet.publicKey
         })
         .rpc();
+    return { payTokenMint, idoTokenMint };
 }
 
-async function createPDA(_buyer: PublicKey) {
+async function createPDA(authorizedSaleAccount: PublicKey, _buyer: PublicKey) {
     const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
         [
             anchor.utils.bytes.utf8.encode("buyer-info"),
+            authorizedSaleAccount.toBuffer(),
             _buyer.toBuffer()
         ],
         program.programId
     );
     await program.methods
-        .creatBuyerInfo(
-            new anchor.BN(0),
-            new anchor.BN(0),
-        )
+        .creatBuyerInfo()
         .accounts({
+            authorizedSaleAccount: authorizedSaleAccount,
             user: provider.wallet.publicKey,
             buyerInfo: buyerInfoPDA,
         })
@@ -65,35 +221,48 @@ describe("solana-mangamon-sale", () => {
     describe("#initialization", function () {
         // Create an account keypair for our program to use.
         const authorizedSaleAccount = anchor.web3.Keypair.generate();
-        const saleAccount = anchor.web3.Keypair.generate();
+        let saleAccount: PublicKey;
         before(async function () {
             try {
-                await initializateAccount(authorizedSaleAccount, saleAccount);
+                saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                await initializateAccount(authorizedSaleAccount);
             } catch (error) {
                 console.log(error);
             }
         });
         it("Checks if everything initialized correctly!", async function () {
+            expect((await tokenProgram.account.token
+                .fetch(await findPayTokenVault(authorizedSaleAccount.publicKey))).amount.toNumber()).to.equal(0);
+            expect((await program.account.authorizedSaleAccount
+                .fetch(authorizedSaleAccount.publicKey)).idoTokenPrice.numerator.toNumber()).to.equal(2);
             expect((await program.account.authorizedSaleAccount
-                .fetch(authorizedSaleAccount.publicKey)).idoTokenPriceRatio.toNumber()).to.equal(4000);
+                .fetch(authorizedSaleAccount.publicKey)).idoTokenPrice.denominator.toNumber()).to.equal(5);
             expect((await program.account.authorizedSaleAccount
-                .fetch(authorizedSaleAccount.publicKey)).idoTokenPriceMultiplier.toNumber()).to.equal(10000);
+                .fetch(authorizedSaleAccount.publicKey)).payTokenDecimals).to.equal(6);
+            expect((await program.account.authorizedSaleAccount
+                .fetch(authorizedSaleAccount.publicKey)).idoTokenDecimals).to.equal(9);
             expect((await program.account.authorizedSaleAccount
                 .fetch(authorizedSaleAccount.publicKey)).initialPercentageAllocationIdoTokens).to.equal(20);
             expect((await program.account.saleAccount
-                .fetch(saleAccount.publicKey)).totalSpendPayTokens.toNumber()).to.equal(0);
+                .fetch(saleAccount)).totalSpendPayTokens.toNumber()).to.equal(0);
+            expect((await program.account.saleAccount
+                .fetch(saleAccount)).totalWithdrawnPayTokens.toNumber()).to.equal(0);
             expect((await program.account.saleAccount
-                .fetch(saleAccount.publicKey)).totalAllocatedIdoTokens.toNumber()).to.equal(0);
+                .fetch(saleAccount)).totalAllocatedIdoTokens.toNumber()).to.equal(0);
             expect((await program.account.authorizedSaleAccount
                 .fetch(authorizedSaleAccount.publicKey)).tokensForSale.toNumber()).to.equal(0);
             expect((await program.account.saleAccount
-                .fetch(saleAccount.publicKey)).investorCount.toNumber()).to.equal(0);
+                .fetch(saleAccount)).investorCount.toNumber()).to.equal(0);
             expect((await program.account.authorizedSaleAccount
                 .fetch(authorizedSaleAccount.publicKey)).startDateFunding.toNumber()).to.equal(1652972400);
             expect((await program.account.authorizedSaleAccount
                 .fetch(authorizedSaleAccount.publicKey)).endDateFunding.toNumber()).to.equal(1654285600);
             expect((await program.account.authorizedSaleAccount
                 .fetch(authorizedSaleAccount.publicKey)).endDateOfClaimingTokens.toNumber()).to.equal(1666504800);
+            expect(String((await program.account.authorizedSaleAccount
+                .fetch(authorizedSaleAccount.publicKey)).hardCapIdoTokens)).to.equal("1000000000000000");
+            expect((await program.account.authorizedSaleAccount
+                .fetch(authorizedSaleAccount.publicKey)).isFundFirst).to.equal(false);
             expect((await program.account.authorizedSaleAccount
                 .fetch(authorizedSaleAccount.publicKey)).isIdoTokenFundedToContract).to.equal(false);
             expect((await program.account.authorizedSaleAccount
@@ -103,10 +272,42 @@ describe("solana-mangamon-sale", () => {
             expect((await program.account.authorizedSaleAccount
                 .fetch(authorizedSaleAccount.publicKey)).isClaimingOpen).to.equal(false);
         });
+        it("Should throw error, as the funding would end after the claiming!", async function () {
+            const otherSaleAccount = anchor.web3.Keypair.generate();
+            let e: any;
+            try {
+                await program.methods
+                    .initialize(
+                        { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                        new anchor.BN(1652972400),
+                        new anchor.BN(1666504800),
+                        new anchor.BN(1654285600),
+                        20,
+                        false,
+                        new anchor.BN("1000000000000000"),
+                        false,
+                    )
+                    .accounts({
+                        authorizedSaleAccount: otherSaleAccount.publicKey,
+                        saleAccount: await findSaleAccount(otherSaleAccount.publicKey),
+                        payTokenMint: await createMint(6),
+                        payTokenVault: await findPayTokenVault(otherSaleAccount.publicKey),
+                        idoTokenMint: await createMint(9),
+                        idoTokenVault: await findIdoTokenVault(otherSaleAccount.publicKey),
+                        trancheSchedule: await findTrancheSchedule(otherSaleAccount.publicKey),
+                        user: provider.wallet.publicKey,
+                    })
+                    .signers([otherSaleAccount])
+                    .rpc();
+            } catch (error) {
+                e = error;
+            }
+            expect(e.error.errorCode.code).to.equal("FundingOverlapsClaiming");
+        });
         it("Should create PDA for a buyer and initialized its field!", async function () {
             let buyerInfoPDA: PublicKey;
             try {
-                buyerInfoPDA = await createPDA(provider.wallet.publicKey);
+                buyerInfoPDA = await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
             } catch (error) {
                 console.log(error)
             }
@@ -124,10 +325,11 @@ describe("solana-mangamon-sale", () => {
         describe("#setInitialPercentageAllocationIdoTokens()", function () {
             // Create an account keypair for our program to use.
             const authorizedSaleAccount = anchor.web3.Keypair.generate();
-            const saleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
             before(async function () {
                 try {
-                    await initializateAccount(authorizedSaleAccount, saleAccount);
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
                 } catch (error) {
                     console.log(error);
                 }
@@ -141,7 +343,7 @@ describe("solana-mangamon-sale", () => {
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             admin: provider.wallet.publicKey
                         })
                         .rpc();
@@ -159,23 +361,24 @@ describe("solana-mangamon-sale", () => {
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             admin: provider.wallet.publicKey
                         })
                         .rpc();
                 } catch (error) {
                     e = error;
                 }
-                expect(JSON.stringify(e).includes("You cannot give more than 100 percent of the token allocation")).to.equal(true);
+                expect(e.error.errorCode.code).to.equal("PercentageAboveHundred");
             });
         });
         describe("#enableClaiming()", function () {
             // Create an account keypair for our program to use.
             const authorizedSaleAccount = anchor.web3.Keypair.generate();
-            const saleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
             before(async function () {
                 try {
-                    await initializateAccount(authorizedSaleAccount, saleAccount);
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
                 } catch (error) {
                     console.log(error);
                 }
@@ -189,7 +392,7 @@ describe("solana-mangamon-sale", () => {
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             admin: provider.wallet.publicKey,
                         })
                         .rpc();
@@ -201,14 +404,55 @@ describe("solana-mangamon-sale", () => {
                 expect((await program.account.authorizedSaleAccount
                     .fetch(authorizedSaleAccount.publicKey)).startDateOfClaimingTokens.toNumber()).to.equal(1656090000);
             });
+            it("Should throw error, as claiming would start after it ends!", async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .enableClaiming(
+                            true,
+                            new anchor.BN(1666504800)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InvalidClaimingDates");
+            });
+            it("Should throw error, as claiming would start before the funding ends!", async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .enableClaiming(
+                            true,
+                            new anchor.BN(1654000000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("FundingOverlapsClaiming");
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).startDateOfClaimingTokens.toNumber()).to.equal(1656090000);
+            });
         });
         describe("#setEndDateOfClaimingTokens()", function () {
             // Create an account keypair for our program to use.
             const authorizedSaleAccount = anchor.web3.Keypair.generate();
-            const saleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
             before(async function () {
                 try {
-                    await initializateAccount(authorizedSaleAccount, saleAccount);
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
                 } catch (error) {
                     console.log(error);
                 }
@@ -217,11 +461,11 @@ describe("solana-mangamon-sale", () => {
                 try {
                     await program.methods
                         .setEndDateOfClaimingTokens(
-                            new anchor.BN(1656090000)
+                            new anchor.BN(1666590000)
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             admin: provider.wallet.publicKey,
                         })
                         .rpc();
@@ -229,19 +473,19 @@ describe("solana-mangamon-sale", () => {
                     console.log(error);
                 }
                 expect((await program.account.authorizedSaleAccount
-                    .fetch(authorizedSaleAccount.publicKey)).endDateOfClaimingTokens.toNumber()).to.equal(1656090000);
+                    .fetch(authorizedSaleAccount.publicKey)).endDateOfClaimingTokens.toNumber()).to.equal(1666590000);
             });
-            it("Should throw error, as claiming is already enabled!", async function () {
+            it("Should throw error, as claiming would end before it starts!", async function () {
                 let e: any;
                 try {
                     await program.methods
                         .enableClaiming(
-                            true,
+                            false,
                             new anchor.BN(1656090000)
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             admin: provider.wallet.publicKey,
                         })
                         .rpc();
@@ -251,282 +495,2092 @@ describe("solana-mangamon-sale", () => {
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             admin: provider.wallet.publicKey,
                         })
                         .rpc();
                 } catch (error) {
                     e = error;
                 }
-                expect(JSON.stringify(e).includes("Claiming is already enabled")).to.equal(true);
+                expect(e.error.errorCode.code).to.equal("InvalidClaimingDates");
             });
-        });
-    });
-    describe("#business logic", function () {
-        describe("#calculateMaxPaymentToken()", function () {
-            // Create an account keypair for our program to use.
-            const authorizedSaleAccount = anchor.web3.Keypair.generate();
-            const saleAccount = anchor.web3.Keypair.generate();
-            before(async function () {
+            it("Should throw error, as claiming would end before the funding ends!", async function () {
+                let e: any;
                 try {
-                    await initializateAccount(authorizedSaleAccount, saleAccount);
+                    await program.methods
+                        .setEndDateOfClaimingTokens(
+                            new anchor.BN(1654285600)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
                 } catch (error) {
-                    console.log(error);
+                    e = error;
                 }
+                expect(e.error.errorCode.code).to.equal("FundingOverlapsClaiming");
             });
-            it("Should calculate the payment token need to acquire the IDO token allocation!", async function () {
-                let returnData: anchor.BN;
+            it("Should throw error, as claiming is already enabled!", async function () {
+                let e: any;
                 try {
-                    returnData = await program.methods
-                        .calculateMaxPaymentToken(
-                            new anchor.BN("22345623767423223324")
+                    await program.methods
+                        .enableClaiming(
+                            true,
+                            new anchor.BN(1656090000)
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
-                            user: provider.wallet.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
                         })
-                        .view();
+                        .rpc();
+                    await program.methods
+                        .setEndDateOfClaimingTokens(
+                            new anchor.BN(1656090000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
                 } catch (error) {
-                    console.log(error)
+                    e = error;
                 }
-                expect(returnData.toNumber()).to.equal(8936000);
+                expect(e.error.errorCode.code).to.equal("ClaimingAlreadyEnabled");
             });
         });
-        describe("#calculateIdoTokensBought()", function () {
+        describe("#setTrancheSchedule()", function () {
             // Create an account keypair for our program to use.
             const authorizedSaleAccount = anchor.web3.Keypair.generate();
-            const saleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
             before(async function () {
                 try {
-                    await initializateAccount(authorizedSaleAccount, saleAccount);
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
                 } catch (error) {
                     console.log(error);
                 }
             });
-            it("Should calculate the amount of IDO token bought!", async function () {
-                let returnData: anchor.BN;
+            it("Should set a monthly unlock table!", async function () {
                 try {
-                    returnData = await program.methods
-                        .calculateIdoTokensBought(
-                            new anchor.BN("8936000")
-                        )
+                    await program.methods
+                        .setTrancheSchedule([
+                            { timestamp: new anchor.BN(1656090000), cumulativeBps: 2500 },
+                            { timestamp: new anchor.BN(1658682000), cumulativeBps: 5000 },
+                            { timestamp: new anchor.BN(1661360400), cumulativeBps: 10000 },
+                        ])
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
-                            user: provider.wallet.publicKey,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            admin: provider.wallet.publicKey,
                         })
-                        .view();
+                        .rpc();
                 } catch (error) {
-                    console.log(error)
+                    console.log(error);
                 }
-                expect(String(returnData)).to.equal("22340000000000000000");
+                const tranches = (await program.account.trancheSchedule
+                    .fetch(await findTrancheSchedule(authorizedSaleAccount.publicKey))).tranches;
+                expect(tranches.length).to.equal(3);
+                expect(tranches[2].cumulativeBps).to.equal(10000);
             });
-        });
-        describe("#fundToContract()", function () {
-            // Create an account keypair for our program to use.
-            const authorizedSaleAccount = anchor.web3.Keypair.generate();
-            const saleAccount = anchor.web3.Keypair.generate();
-            before(async function () {
+            it(`Should throw error saying "The unlock table should have increasing timestamps and shares, ending at 100%"!`, async function () {
+                let e: any;
                 try {
                     await program.methods
-                        .initialize(
-                            new anchor.BN(4000),
-                            new anchor.BN(1652972400),
-                            new anchor.BN(1653646545),
-                            new anchor.BN(1666504800),
-                            20,
-                            false,
-                        )
+                        .setTrancheSchedule([
+                            { timestamp: new anchor.BN(1656090000), cumulativeBps: 5000 },
+                            { timestamp: new anchor.BN(1658682000), cumulativeBps: 9000 },
+                        ])
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
-                            user: provider.wallet.publicKey,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            admin: provider.wallet.publicKey,
                         })
-                        .signers([authorizedSaleAccount, saleAccount])
                         .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InvalidTrancheSchedule");
+            });
+        });
+        describe("#setBuyerVestingOverride()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let buyerInfoPDA: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
+                    buyerInfoPDA = await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
                 } catch (error) {
                     console.log(error);
                 }
             });
-            it("Should calculate the amount of IDO token bought!", async function () {
+            it("Should give the buyer his own vesting!", async function () {
                 try {
                     await program.methods
-                        .fundToContract(
-                            new anchor.BN("14735370000000000000000")
+                        .setBuyerVestingOverride(
+                            provider.wallet.publicKey,
+                            { initialPercentage: 0, cliffDuration: new anchor.BN(2592000), duration: new anchor.BN(31536000) }
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            buyerInfo: buyerInfoPDA,
                             admin: provider.wallet.publicKey,
                         })
                         .rpc();
                 } catch (error) {
-                    console.log(error)
+                    console.log(error);
                 }
-                expect(String((await program.account.authorizedSaleAccount
-                    .fetch(authorizedSaleAccount.publicKey)).tokensForSale)).to.equal("14735370000000000000000");
-                expect((await program.account.authorizedSaleAccount
-                    .fetch(authorizedSaleAccount.publicKey)).isIdoTokenFundedToContract).to.equal(true);
+                expect((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).vestingOverride.cliffDuration.toNumber()).to.equal(2592000);
             });
-            it(`Should throw error, saying "Already funded tokens"!`, async function () {
+            it(`Should throw error saying "The cliff of the vesting override should be between its start and its end"!`, async function () {
                 let e: any;
                 try {
                     await program.methods
-                        .fundToContract(
-                            new anchor.BN("14735370000000000000000")
+                        .setBuyerVestingOverride(
+                            provider.wallet.publicKey,
+                            { initialPercentage: 0, cliffDuration: new anchor.BN(31536001), duration: new anchor.BN(31536000) }
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            buyerInfo: buyerInfoPDA,
                             admin: provider.wallet.publicKey,
                         })
                         .rpc();
                 } catch (error) {
                     e = error;
                 }
-                expect(JSON.stringify(e).includes("Already funded tokens")).to.equal(true);
+                expect(e.error.errorCode.code).to.equal("InvalidVestingOverride");
             });
         });
-        describe("#buy()", function () {
+        describe("#setCliffDateOfClaimingTokens()", function () {
             // Create an account keypair for our program to use.
             const authorizedSaleAccount = anchor.web3.Keypair.generate();
-            const saleAccount = anchor.web3.Keypair.generate();
-            let e: any;
+            let saleAccount: PublicKey;
             before(async function () {
                 try {
-                    await initializateAccount(authorizedSaleAccount, saleAccount);
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
                 } catch (error) {
                     console.log(error);
                 }
             });
-            it("Should let user buy IDO tokens worth of 4000 pay tokens!", async function () {
-                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
-                    [
-                        anchor.utils.bytes.utf8.encode("buyer-info"),
-                        provider.wallet.publicKey.toBuffer()
-                    ],
-                    program.programId
-                );
+            it("Should change the cliff date for tokens to be claimed!", async function () {
                 try {
                     await program.methods
-                        .buy(
-                            new anchor.BN(4000)
+                        .setCliffDateOfClaimingTokens(
+                            new anchor.BN(1660000000)
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
-                            buyerInfo: buyerInfoPDA,
-                            user: provider.wallet.publicKey
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
                         })
                         .rpc();
                 } catch (error) {
                     console.log(error);
                 }
-                expect((await program.account.saleAccount
-                    .fetch(saleAccount.publicKey)).investorCount.toNumber()).to.equal(1);
-                expect(String((await program.account.saleAccount
-                    .fetch(saleAccount.publicKey)).totalSpendPayTokens)).to.equal("4000");
-                expect(String((await program.account.saleAccount
-                    .fetch(saleAccount.publicKey)).totalAllocatedIdoTokens)).to.equal("10000000000000000");
-                expect(String((await program.account.buyerInfo
-                    .fetch(buyerInfoPDA)).spendPayTokens)).to.equal("4000");
-                expect(String((await program.account.buyerInfo
-                    .fetch(buyerInfoPDA)).idoTokensToGet)).to.equal("10000000000000000");
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).cliffDateOfClaimingTokens.toNumber()).to.equal(1660000000);
             });
-            it(`Should throw error saying, "You cannot buy more tokens than is allowed according to your lottery allocation calculation"!`, async function () {
-                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
-                    [
-                        anchor.utils.bytes.utf8.encode("buyer-info"),
-                        provider.wallet.publicKey.toBuffer()
-                    ],
-                    program.programId
-                );
+            it(`Should throw error saying "The cliff date should not be after the end date of claiming"!`, async function () {
+                let e: any;
                 try {
                     await program.methods
-                        .buy(
-                            new anchor.BN(2000)
+                        .setCliffDateOfClaimingTokens(
+                            new anchor.BN(1666504801)
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
-                            buyerInfo: buyerInfoPDA,
-                            user: provider.wallet.publicKey
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
                         })
                         .rpc();
                 } catch (error) {
                     e = error;
                 }
-                expect(JSON.stringify(e).includes("You cannot buy more tokens than is allowed according to your lottery allocation calculation")).to.equal(true);
+                expect(e.error.errorCode.code).to.equal("InvalidCliffDate");
             });
         });
-        describe("#claimTokens()", function () {
+        describe("#setIdoTokenPrice()", function () {
             // Create an account keypair for our program to use.
             const authorizedSaleAccount = anchor.web3.Keypair.generate();
-            const saleAccount = anchor.web3.Keypair.generate();
-            let e: any;
+            const upcomingSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let upcomingSale: PublicKey;
             before(async function () {
                 try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
+                    // A sale whose funding has not started yet
+                    upcomingSale = await findSaleAccount(upcomingSaleAccount.publicKey);
                     await program.methods
                         .initialize(
-                            new anchor.BN(4000),
-                            new anchor.BN(1652972400),
-                            new anchor.BN(1653285600),
-                            new anchor.BN(1666504800),
+                            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                            new anchor.BN(4102444800),
+                            new anchor.BN(4103654400),
+                            new anchor.BN(4133980800),
                             20,
                             false,
+                            new anchor.BN("1000000000000000"),
+                            false,
                         )
                         .accounts({
-                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            payTokenMint: await createMint(6),
+                            payTokenVault: await findPayTokenVault(upcomingSaleAccount.publicKey),
+                            idoTokenMint: await createMint(9),
+                            idoTokenVault: await findIdoTokenVault(upcomingSaleAccount.publicKey),
+                            trancheSchedule: await findTrancheSchedule(upcomingSaleAccount.publicKey),
                             user: provider.wallet.publicKey,
                         })
-                        .signers([authorizedSaleAccount, saleAccount])
+                        .signers([upcomingSaleAccount])
                         .rpc();
                 } catch (error) {
                     console.log(error);
                 }
             });
-            it(`Should throw error saying "Tokens have not been added to the contract YET"!`, async function () {
-                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
-                    [
-                        anchor.utils.bytes.utf8.encode("buyer-info"),
-                        provider.wallet.publicKey.toBuffer()
-                    ],
-                    program.programId
-                );
+            it("Should change the price of the IDO token before the funding starts!", async function () {
                 try {
+                    await program.methods
+                        .setIdoTokenPrice(
+                            { numerator: new anchor.BN(3), denominator: new anchor.BN(7) }
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                const idoTokenPrice = (await program.account.authorizedSaleAccount
+                    .fetch(upcomingSaleAccount.publicKey)).idoTokenPrice;
+                expect(idoTokenPrice.numerator.toNumber()).to.equal(3);
+                expect(idoTokenPrice.denominator.toNumber()).to.equal(7);
+            });
+            it(`Should throw error saying "The price should have a positive numerator and denominator"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setIdoTokenPrice(
+                            { numerator: new anchor.BN(3), denominator: new anchor.BN(0) }
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InvalidPrice");
+            });
+            it(`Should throw error saying "The soft cap should not be above the price of the hard cap"!`, async function () {
+                let e: any;
+                try {
+                    // The hard cap is worth 428571428572 pay tokens at 3/7
+                    await program.methods
+                        .setSoftCapPayTokens(
+                            new anchor.BN("400000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                    await program.methods
+                        .setIdoTokenPrice(
+                            { numerator: new anchor.BN(1), denominator: new anchor.BN(7) }
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("SoftCapAboveHardCap");
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(upcomingSaleAccount.publicKey)).idoTokenPrice.numerator.toNumber()).to.equal(3);
+            });
+            it(`Should throw error saying "The Funding Period has already started"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setIdoTokenPrice(
+                            { numerator: new anchor.BN(3), denominator: new anchor.BN(7) }
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("FundingAlreadyStarted");
+            });
+        });
+        describe("#setFundingDates()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            const upcomingSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let upcomingSale: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
+                    // A sale whose funding has not started yet
+                    upcomingSale = await findSaleAccount(upcomingSaleAccount.publicKey);
+                    await program.methods
+                        .initialize(
+                            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                            new anchor.BN(4102444800),
+                            new anchor.BN(4103654400),
+                            new anchor.BN(4133980800),
+                            20,
+                            false,
+                            new anchor.BN("1000000000000000"),
+                            false,
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            payTokenMint: await createMint(6),
+                            payTokenVault: await findPayTokenVault(upcomingSaleAccount.publicKey),
+                            idoTokenMint: await createMint(9),
+                            idoTokenVault: await findIdoTokenVault(upcomingSaleAccount.publicKey),
+                            trancheSchedule: await findTrancheSchedule(upcomingSaleAccount.publicKey),
+                            user: provider.wallet.publicKey,
+                        })
+                        .signers([upcomingSaleAccount])
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it("Should move the funding dates before the funding starts!", async function () {
+                try {
+                    await program.methods
+                        .setFundingDates(
+                            new anchor.BN(4103049600),
+                            new anchor.BN(4104259200)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(upcomingSaleAccount.publicKey)).startDateFunding.toNumber()).to.equal(4103049600);
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(upcomingSaleAccount.publicKey)).endDateFunding.toNumber()).to.equal(4104259200);
+            });
+            it(`Should throw error, as the funding would end before it starts!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setFundingDates(
+                            new anchor.BN(4104259200),
+                            new anchor.BN(4103049600)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InvalidFundingDates");
+            });
+            it(`Should throw error, as the funding would end after the claiming!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setFundingDates(
+                            new anchor.BN(4103049600),
+                            new anchor.BN(4134585600)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("FundingOverlapsClaiming");
+            });
+            it(`Should throw error, as the funding would start in the past!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setFundingDates(
+                            new anchor.BN(1652972400),
+                            new anchor.BN(4104259200)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("FundingAlreadyStarted");
+            });
+            it(`Should throw error, as the funding has already ended!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setFundingDates(
+                            new anchor.BN(1652972400),
+                            new anchor.BN(1664582400)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("FundingDatesLocked");
+            });
+        });
+        describe("#setSoftCapPayTokens()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            const upcomingSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let upcomingSale: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
+                    // A sale whose funding has not started yet
+                    upcomingSale = await findSaleAccount(upcomingSaleAccount.publicKey);
+                    await program.methods
+                        .initialize(
+                            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                            new anchor.BN(4102444800),
+                            new anchor.BN(4103654400),
+                            new anchor.BN(4133980800),
+                            20,
+                            false,
+                            new anchor.BN("1000000000000000"),
+                            false,
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            payTokenMint: await createMint(6),
+                            payTokenVault: await findPayTokenVault(upcomingSaleAccount.publicKey),
+                            idoTokenMint: await createMint(9),
+                            idoTokenVault: await findIdoTokenVault(upcomingSaleAccount.publicKey),
+                            trancheSchedule: await findTrancheSchedule(upcomingSaleAccount.publicKey),
+                            user: provider.wallet.publicKey,
+                        })
+                        .signers([upcomingSaleAccount])
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it("Should set the soft cap before the funding starts!", async function () {
+                try {
+                    await program.methods
+                        .setSoftCapPayTokens(
+                            new anchor.BN("100000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect(String((await program.account.authorizedSaleAccount
+                    .fetch(upcomingSaleAccount.publicKey)).softCapPayTokens)).to.equal("100000000000");
+            });
+            it(`Should throw error saying "The soft cap should not be above the price of the hard cap"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setSoftCapPayTokens(
+                            new anchor.BN("400000000001")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: upcomingSaleAccount.publicKey,
+                            saleAccount: upcomingSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("SoftCapAboveHardCap");
+            });
+            it(`Should throw error saying "The Funding Period has already started"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setSoftCapPayTokens(
+                            new anchor.BN("100000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("FundingAlreadyStarted");
+            });
+            it("Should not fail an ended funding without a soft cap!", async function () {
+                let returnData: boolean;
+                try {
+                    returnData = await program.methods
+                        .isFundingFailed()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                        })
+                        .view();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(returnData).to.equal(false);
+            });
+        });
+    });
+    describe("#business logic", function () {
+        describe("#calculateMaxPaymentToken()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it("Should calculate the payment token need to acquire the IDO token allocation!", async function () {
+                let returnData: anchor.BN;
+                try {
+                    returnData = await program.methods
+                        .calculateMaxPaymentToken(
+                            new anchor.BN("22345623767")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                        })
+                        .view();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(returnData.toNumber()).to.equal(8938250);
+            });
+        });
+        describe("#calculateIdoTokensBought()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it("Should calculate the amount of IDO token bought!", async function () {
+                let returnData: anchor.BN;
+                try {
+                    returnData = await program.methods
+                        .calculateIdoTokensBought(
+                            new anchor.BN("8936000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                        })
+                        .view();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(String(returnData)).to.equal("22340000000");
+            });
+        });
+        describe("#fundToContract()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let adminIdoTokenAccount: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    const idoTokenMint = await createMint(9);
+                    adminIdoTokenAccount = await createTokenAccount(
+                        idoTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN("14735370000000000")
+                    );
+                    await program.methods
+                        .initialize(
+                            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                            new anchor.BN(1652972400),
+                            new anchor.BN(1653646545),
+                            new anchor.BN(1666504800),
+                            20,
+                            false,
+                            new anchor.BN("1000000000000000"),
+                            false,
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            payTokenMint: await createMint(6),
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            user: provider.wallet.publicKey,
+                        })
+                        .signers([authorizedSaleAccount])
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error, saying "You do not have enough IDO tokens to fund the contract"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .fundToContract(
+                            new anchor.BN("14735370000000001")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InsufficientIdoTokens");
+            });
+            it("Should calculate the amount of IDO token bought!", async function () {
+                try {
+                    await program.methods
+                        .fundToContract(
+                            new anchor.BN("14735370000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(String((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).tokensForSale)).to.equal("14735370000000000");
+                expect(String((await tokenProgram.account.token
+                    .fetch(await findIdoTokenVault(authorizedSaleAccount.publicKey))).amount)).to.equal("14735370000000000");
+                expect((await tokenProgram.account.token
+                    .fetch(adminIdoTokenAccount)).amount.toNumber()).to.equal(0);
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).isIdoTokenFundedToContract).to.equal(true);
+            });
+            it(`Should throw error, saying "Already funded tokens"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .fundToContract(
+                            new anchor.BN("14735370000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("AlreadyFunded");
+            });
+            it("Should let admin withdraw unsold IDO tokens!", async function () {
+                try {
+                    await program.methods
+                        .withdrawUnsoldIdoTokens(
+                            new anchor.BN("4735370000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(String((await program.account.saleAccount
+                    .fetch(saleAccount)).totalWithdrawnUnsoldIdoTokens)).to.equal("4735370000000000");
+                expect(String((await tokenProgram.account.token
+                    .fetch(await findIdoTokenVault(authorizedSaleAccount.publicKey))).amount)).to.equal("10000000000000000");
+            });
+            it(`Should throw error, saying "You cannot withdraw more IDO tokens than are unsold"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .withdrawUnsoldIdoTokens(
+                            new anchor.BN("10000000000000001")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("WithdrawExceedsUnsoldIdoTokens");
+            });
+        });
+        describe("#fundToContract() funded first", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let adminIdoTokenAccount: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    const idoTokenMint = await createMint(9);
+                    adminIdoTokenAccount = await createTokenAccount(
+                        idoTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN("14735370000000000")
+                    );
+                    // Funding has not started, nor ended, yet
+                    await program.methods
+                        .initialize(
+                            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                            new anchor.BN(4102444800),
+                            new anchor.BN(4103654400),
+                            new anchor.BN(4133980800),
+                            20,
+                            false,
+                            new anchor.BN("10000000000000000"),
+                            true,
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            payTokenMint: await createMint(6),
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            user: provider.wallet.publicKey,
+                        })
+                        .signers([authorizedSaleAccount])
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error, saying "Funded first, you should at least match the hard cap"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .fundToContract(
+                            new anchor.BN("4735370000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("FundingBelowHardCap");
+            });
+            it("Should let admin fund the contract before the funding!", async function () {
+                try {
+                    await program.methods
+                        .fundToContract(
+                            new anchor.BN("10000000000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(String((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).tokensForSale)).to.equal("10000000000000000");
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).isIdoTokenFundedToContract).to.equal(true);
+            });
+            it("Should let admin recover the funded IDO tokens once the sale is canceled!", async function () {
+                try {
+                    await program.methods
+                        .cancelIdoSale()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                    await program.methods
+                        .withdrawUnsoldIdoTokens(
+                            new anchor.BN("10000000000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(String((await program.account.saleAccount
+                    .fetch(saleAccount)).totalWithdrawnUnsoldIdoTokens)).to.equal("10000000000000000");
+                expect(String((await tokenProgram.account.token
+                    .fetch(adminIdoTokenAccount)).amount)).to.equal("14735370000000000");
+            });
+        });
+        describe("#lottery", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            const secret = Buffer.alloc(32, 42);
+            let lotteryDrawSlot: number;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
+                    lotteryDrawSlot = await provider.connection.getSlot() + 30;
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "The lottery registration should start before it ends, and end before the funding starts"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setupLottery(
+                            new anchor.BN(1652000000),
+                            new anchor.BN(1653000000),
+                            new anchor.BN(1),
+                            [...createHash("sha256").update(secret).digest()],
+                            new anchor.BN(lotteryDrawSlot)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InvalidLotteryDates");
+            });
+            it(`Should throw error saying "The lottery draw slot should be in the future"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setupLottery(
+                            new anchor.BN(1652000000),
+                            new anchor.BN(1652900000),
+                            new anchor.BN(1),
+                            [...createHash("sha256").update(secret).digest()],
+                            new anchor.BN(await provider.connection.getSlot())
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InvalidLotteryDrawSlot");
+            });
+            it(`Should throw error saying "The lottery registration is not open"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setupLottery(
+                            new anchor.BN(1652000000),
+                            new anchor.BN(1652900000),
+                            new anchor.BN(1),
+                            [...createHash("sha256").update(secret).digest()],
+                            new anchor.BN(lotteryDrawSlot)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                    await program.methods
+                        .registerForLottery()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            lotteryTicket: await findLotteryTicket(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("LotteryRegistrationNotOpen");
+            });
+            it(`Should throw error saying "The revealed secret does not match the lottery commitment"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .revealLotterySecret([...Buffer.alloc(32, 1)])
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InvalidLotteryReveal");
+            });
+            it(`Should throw error saying "The lottery draw slot has not passed yet"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .drawLottery()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            recentSlotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("LotteryDrawSlotNotReached");
+            });
+            it("Should reveal the committed secret before the draw slot!", async function () {
+                try {
+                    await program.methods
+                        .revealLotterySecret([...secret])
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).isLotterySecretRevealed).to.equal(true);
+            });
+            it("Should let anyone draw the lottery once the draw slot has passed!", async function () {
+                try {
+                    await waitUntilSlotAfter(lotteryDrawSlot);
+                    await program.methods
+                        .drawLottery()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            recentSlotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).isLotteryPlayed).to.equal(true);
+            });
+            it(`Should throw error saying "The lottery secret can only be revealed after the registration, before the draw slot"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .revealLotterySecret([...secret])
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("LotteryRevealClosed");
+            });
+            it(`Should throw error saying "The lottery has already been played"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .drawLottery()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            recentSlotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("LotteryAlreadyPlayed");
+            });
+        });
+        describe("#buy()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let userPayTokenAccount: PublicKey;
+            let e: any;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    const { payTokenMint } = await initializateAccount(authorizedSaleAccount);
+                    await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
+                    userPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN(10000)
+                    );
+                    await program.methods
+                        .setAllocation(
+                            provider.wallet.publicKey,
+                            new anchor.BN("10000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            authority: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it("Should let user buy IDO tokens worth of 4000 pay tokens!", async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                try {
+                    await program.methods
+                        .buy(
+                            new anchor.BN(4000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect((await tokenProgram.account.token
+                    .fetch(await findPayTokenVault(authorizedSaleAccount.publicKey))).amount.toNumber()).to.equal(4000);
+                expect((await tokenProgram.account.token
+                    .fetch(userPayTokenAccount)).amount.toNumber()).to.equal(6000);
+                expect((await program.account.saleAccount
+                    .fetch(saleAccount)).investorCount.toNumber()).to.equal(1);
+                expect(String((await program.account.saleAccount
+                    .fetch(saleAccount)).totalSpendPayTokens)).to.equal("4000");
+                expect(String((await program.account.saleAccount
+                    .fetch(saleAccount)).totalAllocatedIdoTokens)).to.equal("10000000");
+                expect(String((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).spendPayTokens)).to.equal("4000");
+                expect(String((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).idoTokensToGet)).to.equal("10000000");
+            });
+            it(`Should throw error saying, "You cannot buy more tokens than is allowed according to your lottery allocation calculation"!`, async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                try {
+                    await program.methods
+                        .buy(
+                            new anchor.BN(2000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("AllocationExceeded");
+            });
+            it("Should add the buyer to the first page of the buyer registry!", async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                const buyerRegistryPage = await findBuyerRegistryPage(authorizedSaleAccount.publicKey, new anchor.BN(0));
+                try {
+                    await program.methods
+                        .addToBuyerRegistry(
+                            provider.wallet.publicKey,
+                            new anchor.BN(0)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            buyerRegistryPage: buyerRegistryPage,
+                            payer: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect((await program.account.buyerRegistryPage
+                    .fetch(buyerRegistryPage)).buyers[0].toBase58()).to.equal(provider.wallet.publicKey.toBase58());
+                expect((await program.account.saleAccount
+                    .fetch(saleAccount)).buyerRegistryCount.toNumber()).to.equal(1);
+            });
+            it(`Should throw error saying "Only the admin or the allocation authority can set allocations"!`, async function () {
+                const stranger = anchor.web3.Keypair.generate();
+                await provider.connection.confirmTransaction(
+                    await provider.connection.requestAirdrop(stranger.publicKey, 1000000000)
+                );
+                try {
+                    await program.methods
+                        .setAllocation(
+                            stranger.publicKey,
+                            new anchor.BN("10000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, stranger.publicKey),
+                            authority: stranger.publicKey,
+                        })
+                        .signers([stranger])
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("NotAllocationAuthority");
+            });
+            it("Should check if the provided wallet address is buyer!", async function () {
+                let returnData: boolean;
+                try {
+                    returnData = await program.methods
+                        .isBuyer(
+                            provider.wallet.publicKey
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            buyerInfo: (await PublicKey.findProgramAddress(
+                                [
+                                    anchor.utils.bytes.utf8.encode("buyer-info"),
+                                    authorizedSaleAccount.publicKey.toBuffer(),
+                                    provider.wallet.publicKey.toBuffer()
+                                ],
+                                program.programId
+                            ))[0],
+                        })
+                        .view();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(returnData).to.equal(true);
+            });
+            it("Should not take a wallet address that never bought for a buyer!", async function () {
+                const stranger = anchor.web3.Keypair.generate();
+                let returnData: boolean;
+                try {
+                    returnData = await program.methods
+                        .isBuyer(
+                            stranger.publicKey
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            buyerInfo: (await PublicKey.findProgramAddress(
+                                [
+                                    anchor.utils.bytes.utf8.encode("buyer-info"),
+                                    authorizedSaleAccount.publicKey.toBuffer(),
+                                    stranger.publicKey.toBuffer()
+                                ],
+                                program.programId
+                            ))[0],
+                        })
+                        .view();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(returnData).to.equal(false);
+            });
+            it("Should not return claimable tokens until claiming is open!", async function () {
+                let returnData: anchor.BN;
+                try {
+                    returnData = await program.methods
+                        .getClaimableTokens(
+                            provider.wallet.publicKey
+                        )
+                        .accounts({
+                            buyerInfo: (await PublicKey.findProgramAddress(
+                                [
+                                    anchor.utils.bytes.utf8.encode("buyer-info"),
+                                    authorizedSaleAccount.publicKey.toBuffer(),
+                                    provider.wallet.publicKey.toBuffer()
+                                ],
+                                program.programId
+                            ))[0],
+                            saleAccount: saleAccount,
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                        })
+                        .view();
+                } catch (error) {
+                    console.log(error)
+                }
+                // The vesting has already ended, but the claiming has not been enabled
+                expect(String(returnData)).to.equal("0");
+            });
+        });
+        describe("#buy() against the hard cap", function () {
+            // Create an account keypair for our program to use.
+            const cappedSaleAccount = anchor.web3.Keypair.generate();
+            const fundFirstSaleAccount = anchor.web3.Keypair.generate();
+            let cappedSale: PublicKey;
+            let fundFirstSale: PublicKey;
+            let userPayTokenAccount: PublicKey;
+            before(async function () {
+                try {
+                    const now = await chainTime();
+                    const payTokenMint = await createMint(6);
+                    userPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN(10000)
+                    );
+                    // Both sales are open, with a hard cap worth 4000 pay tokens
+                    cappedSale = await findSaleAccount(cappedSaleAccount.publicKey);
+                    fundFirstSale = await findSaleAccount(fundFirstSaleAccount.publicKey);
+                    for (const [authorizedSaleAccount, saleAccount, isFundFirst] of [
+                        [cappedSaleAccount, cappedSale, false],
+                        [fundFirstSaleAccount, fundFirstSale, true],
+                    ] as [anchor.web3.Keypair, PublicKey, boolean][]) {
+                        await program.methods
+                            .initialize(
+                                { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                                new anchor.BN(now - 10),
+                                new anchor.BN(now + 3600),
+                                new anchor.BN(now + 7200),
+                                20,
+                                false,
+                                new anchor.BN("10000000"),
+                                isFundFirst,
+                            )
+                            .accounts({
+                                authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                                saleAccount: saleAccount,
+                                payTokenMint: payTokenMint,
+                                payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                                idoTokenMint: await createMint(9),
+                                idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                                trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                                user: provider.wallet.publicKey,
+                            })
+                            .signers([authorizedSaleAccount])
+                            .rpc();
+                        await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
+                        await program.methods
+                            .setAllocation(
+                                provider.wallet.publicKey,
+                                new anchor.BN("20000000")
+                            )
+                            .accounts({
+                                authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                                allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                                authority: provider.wallet.publicKey,
+                            })
+                            .rpc();
+                    }
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "You cannot buy more tokens than the hard cap of the sale"!`, async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        cappedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                let e: any;
+                try {
+                    await program.methods
+                        .buy(
+                            new anchor.BN(8000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: cappedSaleAccount.publicKey,
+                            saleAccount: cappedSale,
+                            buyerInfo: buyerInfoPDA,
+                            allocation: await findAllocation(cappedSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(cappedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("HardCapExceeded");
+                expect(String((await program.account.saleAccount
+                    .fetch(cappedSale)).totalAllocatedIdoTokens)).to.equal("0");
+            });
+            it(`Should throw error saying "Tokens have not been added to the contract YET" when funded first!`, async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        fundFirstSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                let e: any;
+                try {
+                    await program.methods
+                        .buy(
+                            new anchor.BN(4000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: fundFirstSaleAccount.publicKey,
+                            saleAccount: fundFirstSale,
+                            buyerInfo: buyerInfoPDA,
+                            allocation: await findAllocation(fundFirstSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(fundFirstSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("IdoTokensNotFunded");
+                expect(String((await program.account.saleAccount
+                    .fetch(fundFirstSale)).totalAllocatedIdoTokens)).to.equal("0");
+            });
+        });
+        describe("#buy() in two sales", function () {
+            // Create an account keypair for our program to use.
+            const firstSaleAccount = anchor.web3.Keypair.generate();
+            const secondSaleAccount = anchor.web3.Keypair.generate();
+            let firstSale: PublicKey;
+            let secondSale: PublicKey;
+            let firstBuyerInfo: PublicKey;
+            let secondBuyerInfo: PublicKey;
+            let payTokenMint: PublicKey;
+            let userPayTokenAccount: PublicKey;
+            before(async function () {
+                try {
+                    const now = await chainTime();
+                    payTokenMint = await createMint(6);
+                    userPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN(10000)
+                    );
+                    // The same wallet buys in both sales, open at the same time
+                    firstSale = await findSaleAccount(firstSaleAccount.publicKey);
+                    secondSale = await findSaleAccount(secondSaleAccount.publicKey);
+                    for (const [authorizedSaleAccount, saleAccount] of [
+                        [firstSaleAccount, firstSale],
+                        [secondSaleAccount, secondSale],
+                    ] as [anchor.web3.Keypair, PublicKey][]) {
+                        await program.methods
+                            .initialize(
+                                { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                                new anchor.BN(now - 10),
+                                new anchor.BN(now + 3600),
+                                new anchor.BN(now + 7200),
+                                20,
+                                false,
+                                new anchor.BN("1000000000000000"),
+                                false,
+                            )
+                            .accounts({
+                                authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                                saleAccount: saleAccount,
+                                payTokenMint: payTokenMint,
+                                payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                                idoTokenMint: await createMint(9),
+                                idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                                trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                                user: provider.wallet.publicKey,
+                            })
+                            .signers([authorizedSaleAccount])
+                            .rpc();
+                        await program.methods
+                            .setAllocation(
+                                provider.wallet.publicKey,
+                                new anchor.BN("10000000")
+                            )
+                            .accounts({
+                                authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                                allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                                authority: provider.wallet.publicKey,
+                            })
+                            .rpc();
+                    }
+                    firstBuyerInfo = await createPDA(firstSaleAccount.publicKey, provider.wallet.publicKey);
+                    secondBuyerInfo = await createPDA(secondSaleAccount.publicKey, provider.wallet.publicKey);
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it("Should keep a buyer info of the same wallet in each sale!", async function () {
+                try {
+                    for (const [authorizedSaleAccount, saleAccount, buyerInfo, amount] of [
+                        [firstSaleAccount, firstSale, firstBuyerInfo, 4000],
+                        [secondSaleAccount, secondSale, secondBuyerInfo, 2000],
+                    ] as [anchor.web3.Keypair, PublicKey, PublicKey, number][]) {
+                        await program.methods
+                            .buy(
+                                new anchor.BN(amount)
+                            )
+                            .accounts({
+                                authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                                saleAccount: saleAccount,
+                                buyerInfo: buyerInfo,
+                                allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                                payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                                userPayTokenAccount: userPayTokenAccount,
+                                user: provider.wallet.publicKey
+                            })
+                            .rpc();
+                    }
+                } catch (error) {
+                    console.log(error);
+                }
+                expect(firstBuyerInfo.toBase58()).to.not.equal(secondBuyerInfo.toBase58());
+                expect(String((await program.account.buyerInfo
+                    .fetch(firstBuyerInfo)).spendPayTokens)).to.equal("4000");
+                expect(String((await program.account.buyerInfo
+                    .fetch(secondBuyerInfo)).spendPayTokens)).to.equal("2000");
+                expect(String((await program.account.saleAccount
+                    .fetch(firstSale)).totalSpendPayTokens)).to.equal("4000");
+                expect(String((await program.account.saleAccount
+                    .fetch(secondSale)).totalSpendPayTokens)).to.equal("2000");
+            });
+            it("Should throw error, as the buyer info belongs to the other sale!", async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .buy(
+                            new anchor.BN(1000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: secondSaleAccount.publicKey,
+                            saleAccount: secondSale,
+                            buyerInfo: firstBuyerInfo,
+                            allocation: await findAllocation(secondSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(secondSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("ConstraintSeeds");
+                expect(String((await program.account.buyerInfo
+                    .fetch(firstBuyerInfo)).spendPayTokens)).to.equal("4000");
+                expect(String((await program.account.saleAccount
+                    .fetch(secondSale)).totalSpendPayTokens)).to.equal("2000");
+            });
+            it("Should throw error, as the sale account of the other sale is passed to the buyer!", async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .buy(
+                            new anchor.BN(1000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: firstSaleAccount.publicKey,
+                            saleAccount: secondSale,
+                            buyerInfo: firstBuyerInfo,
+                            allocation: await findAllocation(firstSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(firstSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("ConstraintHasOne");
+                expect(String((await program.account.saleAccount
+                    .fetch(secondSale)).totalSpendPayTokens)).to.equal("2000");
+            });
+            it("Should throw error, as the sale account of the other sale is passed to the admin!", async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .setSoftCapPayTokens(
+                            new anchor.BN(1000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: firstSaleAccount.publicKey,
+                            saleAccount: secondSale,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("ConstraintHasOne");
+            });
+            it("Should read what another wallet bought and spent!", async function () {
+                const buyer = anchor.web3.Keypair.generate();
+                const buyerInfo = (await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        firstSaleAccount.publicKey.toBuffer(),
+                        buyer.publicKey.toBuffer()
+                    ],
+                    program.programId
+                ))[0];
+                let returnData: anchor.BN[];
+                try {
+                    await provider.connection.confirmTransaction(
+                        await provider.connection.requestAirdrop(buyer.publicKey, 1000000000)
+                    );
+                    const buyerPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        buyer.publicKey,
+                        new anchor.BN(1000)
+                    );
+                    await program.methods
+                        .setAllocation(
+                            buyer.publicKey,
+                            new anchor.BN("10000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: firstSaleAccount.publicKey,
+                            allocation: await findAllocation(firstSaleAccount.publicKey, buyer.publicKey),
+                            authority: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                    await program.methods
+                        .creatBuyerInfo()
+                        .accounts({
+                            authorizedSaleAccount: firstSaleAccount.publicKey,
+                            user: buyer.publicKey,
+                            buyerInfo: buyerInfo,
+                        })
+                        .signers([buyer])
+                        .rpc();
+                    await program.methods
+                        .buy(
+                            new anchor.BN(1000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: firstSaleAccount.publicKey,
+                            saleAccount: firstSale,
+                            buyerInfo: buyerInfo,
+                            allocation: await findAllocation(firstSaleAccount.publicKey, buyer.publicKey),
+                            payTokenVault: await findPayTokenVault(firstSaleAccount.publicKey),
+                            userPayTokenAccount: buyerPayTokenAccount,
+                            user: buyer.publicKey
+                        })
+                        .signers([buyer])
+                        .rpc();
+                    // Simulated by the provider wallet, as a dashboard would
+                    returnData = await program.methods
+                        .getTotalIdoTokensBoughtAndPayTokensSpend(
+                            buyer.publicKey
+                        )
+                        .accounts({
+                            buyerInfo: buyerInfo,
+                            saleAccount: firstSale,
+                            authorizedSaleAccount: firstSaleAccount.publicKey,
+                            trancheSchedule: await findTrancheSchedule(firstSaleAccount.publicKey),
+                        })
+                        .view();
+                } catch (error) {
+                    console.log(error)
+                }
+                expect(String(returnData[0])).to.equal("2500000");
+                expect(String(returnData[1])).to.equal("1000");
+            });
+        });
+        describe("#buy() as a lottery winner", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let userPayTokenAccount: PublicKey;
+            let buyerInfoPDA: PublicKey;
+            const secret = Buffer.alloc(32, 9);
+            let startDateFunding: number;
+            let lotteryDrawSlot: number;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    const now = await chainTime();
+                    startDateFunding = now + 10;
+                    // Well after the registration ends, so the secret can be revealed in between
+                    lotteryDrawSlot = await provider.connection.getSlot() + 100;
+                    const payTokenMint = await createMint(6);
+                    await program.methods
+                        .initialize(
+                            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                            new anchor.BN(startDateFunding),
+                            new anchor.BN(now + 3600),
+                            new anchor.BN(now + 7200),
+                            20,
+                            false,
+                            new anchor.BN("10000000"),
+                            false,
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            payTokenMint: payTokenMint,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            idoTokenMint: await createMint(9),
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            user: provider.wallet.publicKey,
+                        })
+                        .signers([authorizedSaleAccount])
+                        .rpc();
+                    await program.methods
+                        .setupLottery(
+                            new anchor.BN(now - 10),
+                            new anchor.BN(startDateFunding),
+                            new anchor.BN(1),
+                            [...createHash("sha256").update(secret).digest()],
+                            new anchor.BN(lotteryDrawSlot)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                    await program.methods
+                        .registerForLottery()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            lotteryTicket: await findLotteryTicket(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                    buyerInfoPDA = await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
+                    userPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN(10000)
+                    );
+                    // The registration ends when the funding starts
+                    await waitUntilAfter(startDateFunding);
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "The lottery has not been played yet"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .buy(
+                            new anchor.BN(4000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("LotteryNotPlayed");
+            });
+            it("Should let the lottery winner buy his allocation!", async function () {
+                try {
+                    await program.methods
+                        .revealLotterySecret([...secret])
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                    await waitUntilSlotAfter(lotteryDrawSlot);
+                    await program.methods
+                        .drawLottery()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            recentSlotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY
+                        })
+                        .rpc();
+                    // The only ticket always wins
+                    await program.methods
+                        .markLotteryWinner(provider.wallet.publicKey)
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            lotteryTicket: await findLotteryTicket(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            payer: provider.wallet.publicKey
+                        })
+                        .rpc();
+                    await program.methods
+                        .buy(
+                            new anchor.BN(4000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect(String((await program.account.allocation
+                    .fetch(await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey))).idoTokensAllocation)).to.equal("10000000");
+                expect(String((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).idoTokensToGet)).to.equal("10000000");
+                expect((await tokenProgram.account.token
+                    .fetch(await findPayTokenVault(authorizedSaleAccount.publicKey))).amount.toNumber()).to.equal(4000);
+            });
+        });
+        describe("#buyWithMerkleProof()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            const maxAllocation = new anchor.BN("10000000");
+            let userPayTokenAccount: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    const { payTokenMint } = await initializateAccount(authorizedSaleAccount);
+                    await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
+                    userPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN(10000)
+                    );
+                    // A whitelist of one, so the root is the leaf itself and the proof is empty
+                    await program.methods
+                        .setAllocationMerkleRoot(
+                            [...whitelistLeaf(provider.wallet.publicKey, maxAllocation)]
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "The merkle proof does not match this buyer and allocation"!`, async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                let e: any;
+                try {
+                    await program.methods
+                        .buyWithMerkleProof(
+                            new anchor.BN(4000),
+                            new anchor.BN("20000000"),
+                            []
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("InvalidMerkleProof");
+            });
+            it("Should let a whitelisted user buy IDO tokens worth of 4000 pay tokens!", async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                try {
+                    await program.methods
+                        .buyWithMerkleProof(
+                            new anchor.BN(4000),
+                            maxAllocation,
+                            []
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect((await tokenProgram.account.token
+                    .fetch(await findPayTokenVault(authorizedSaleAccount.publicKey))).amount.toNumber()).to.equal(4000);
+                expect(String((await program.account.saleAccount
+                    .fetch(saleAccount)).totalAllocatedIdoTokens)).to.equal("10000000");
+            });
+        });
+        describe("#claimTokens()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let adminIdoTokenAccount: PublicKey;
+            let idoTokenMint: PublicKey;
+            let e: any;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    idoTokenMint = await createMint(9);
+                    adminIdoTokenAccount = await createTokenAccount(
+                        idoTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN("14735370000000000")
+                    );
+                    await program.methods
+                        .initialize(
+                            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                            new anchor.BN(1652972400),
+                            new anchor.BN(1653285600),
+                            new anchor.BN(1666504800),
+                            20,
+                            false,
+                            new anchor.BN("1000000000000000"),
+                            false,
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            payTokenMint: await createMint(6),
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            user: provider.wallet.publicKey,
+                        })
+                        .signers([authorizedSaleAccount])
+                        .rpc();
+                    await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "Tokens have not been added to the contract YET"!`, async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                try {
+                    await program.methods
+                        .claimTokens()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            userIdoTokenAccount: await anchor.utils.token.associatedAddress({
+                                mint: idoTokenMint,
+                                owner: provider.wallet.publicKey
+                            }),
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("IdoTokensNotFunded");
+            });
+            it(`Should throw error saying "Cannot claim, you need to wait until claiming is enabled"!`, async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                try {
+                    await program.methods
+                        .fundToContract(
+                            new anchor.BN("14735370000000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: adminIdoTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
                     await program.methods
                         .claimTokens()
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             buyerInfo: buyerInfoPDA,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            userIdoTokenAccount: await anchor.utils.token.associatedAddress({
+                                mint: idoTokenMint,
+                                owner: provider.wallet.publicKey
+                            }),
                             user: provider.wallet.publicKey
                         })
                         .rpc();
                 } catch (error) {
                     e = error;
                 }
-                const stringifiedError = JSON.stringify(e);
-                expect(stringifiedError.includes("Tokens have not been added to the contract YET")).to.equal(true);
+                expect(e.error.errorCode.code).to.equal("ClaimingNotOpen");
             });
-            it(`Should throw error saying "Cannot claim, you need to wait until claiming is enabled"!`, async function () {
+            it(`Should throw error saying "You are not a buyer"!`, async function () {
                 const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
                     [
                         anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
                         provider.wallet.publicKey.toBuffer()
                     ],
                     program.programId
                 );
                 try {
                     await program.methods
-                        .fundToContract(
-                            new anchor.BN("14735370000000000000000")
+                        .enableClaiming(
+                            true,
+                            new anchor.BN(1656090000)
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             admin: provider.wallet.publicKey,
                         })
                         .rpc();
@@ -534,25 +2588,170 @@ describe("solana-mangamon-sale", () => {
                         .claimTokens()
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             buyerInfo: buyerInfoPDA,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            userIdoTokenAccount: await anchor.utils.token.associatedAddress({
+                                mint: idoTokenMint,
+                                owner: provider.wallet.publicKey
+                            }),
                             user: provider.wallet.publicKey
                         })
                         .rpc();
                 } catch (error) {
                     e = error;
                 }
-                const stringifiedError = JSON.stringify(e);
-                expect(stringifiedError.includes("Cannot claim, you need to wait until claiming is enabled")).to.equal(true);
+                expect(e.error.errorCode.code).to.equal("NotABuyer");
             });
-            it(`Should throw error saying "You are not a buyer"!`, async function () {
+            it(`Should not let a self-created buyer info claim forged amounts!`, async function () {
+                const forger = anchor.web3.Keypair.generate();
+                await provider.connection.confirmTransaction(
+                    await provider.connection.requestAirdrop(forger.publicKey, 1000000000)
+                );
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        forger.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                let e: any;
+                try {
+                    // The amounts the instruction used to take, appended by hand
+                    const instruction = await program.methods
+                        .creatBuyerInfo()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            user: forger.publicKey,
+                            buyerInfo: buyerInfoPDA,
+                        })
+                        .instruction();
+                    instruction.data = Buffer.concat([
+                        instruction.data,
+                        new anchor.BN(4000).toArrayLike(Buffer, "le", 16),
+                        new anchor.BN("14735370000000000").toArrayLike(Buffer, "le", 16),
+                    ]);
+                    await provider.sendAndConfirm(
+                        new anchor.web3.Transaction().add(instruction),
+                        [forger]
+                    );
+                    await program.methods
+                        .claimTokens()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            userIdoTokenAccount: await anchor.utils.token.associatedAddress({
+                                mint: idoTokenMint,
+                                owner: forger.publicKey
+                            }),
+                            user: forger.publicKey
+                        })
+                        .signers([forger])
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(String((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).spendPayTokens)).to.equal("0");
+                expect(String((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).idoTokensToGet)).to.equal("0");
+                expect(e.error.errorCode.code).to.equal("NotABuyer");
+            });
+            it("Should let buyer claim all IDO tokens he has bought!", async function () {
                 const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
                     [
                         anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
                         provider.wallet.publicKey.toBuffer()
                     ],
                     program.programId
                 );
+                try {
+                    await program.methods
+                        .claimTokens()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            userIdoTokenAccount: await anchor.utils.token.associatedAddress({
+                                mint: idoTokenMint,
+                                owner: provider.wallet.publicKey
+                            }),
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(String((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).idoTokensClaimed)).to.equal("0");
+            });
+        });
+        describe("#withdrawPayTokens()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let treasuryPayTokenAccount: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    const { payTokenMint } = await initializateAccount(authorizedSaleAccount);
+                    treasuryPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN(0)
+                    );
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "You cannot withdraw more pay tokens than the buyers have paid"!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .withdrawPayTokens(
+                            new anchor.BN(4000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            treasuryPayTokenAccount: treasuryPayTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("WithdrawExceedsRaisedPayTokens");
+                expect((await program.account.saleAccount
+                    .fetch(saleAccount)).totalWithdrawnPayTokens.toNumber()).to.equal(0);
+            });
+        });
+        describe("#cancelIdoSale()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    await initializateAccount(authorizedSaleAccount);
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "The sale cannot be canceled once pay tokens are withdrawn or claiming has opened"!`, async function () {
+                let e: any;
                 try {
                     await program.methods
                         .enableClaiming(
@@ -561,48 +2760,319 @@ describe("solana-mangamon-sale", () => {
                         )
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             admin: provider.wallet.publicKey,
                         })
                         .rpc();
                     await program.methods
-                        .claimTokens()
+                        .cancelIdoSale()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("SaleAlreadySettled");
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).isFundingCanceled).to.equal(false);
+            });
+            it(`Should not cancel the sale once claiming has been closed again, as buyers may have claimed!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .enableClaiming(
+                            false,
+                            new anchor.BN(1656090000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                    await program.methods
+                        .cancelIdoSale()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("SaleAlreadySettled");
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).isFundingCanceled).to.equal(false);
+            });
+        });
+        describe("#claimPayedTokensOnIdoCancel()", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let userPayTokenAccount: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    const { payTokenMint } = await initializateAccount(authorizedSaleAccount);
+                    await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
+                    userPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN(0)
+                    );
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "Funding has not been canceled"!`, async function () {
+                const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
+                    [
+                        anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
+                        provider.wallet.publicKey.toBuffer()
+                    ],
+                    program.programId
+                );
+                let e: any;
+                try {
+                    await program.methods
+                        .claimPayedTokensOnIdoCancel()
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             buyerInfo: buyerInfoPDA,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
                             user: provider.wallet.publicKey
                         })
                         .rpc();
                 } catch (error) {
                     e = error;
                 }
-                const stringifiedError = JSON.stringify(e);
-                expect(stringifiedError.includes("You are not a buyer")).to.equal(true);
+                expect(e.error.errorCode.code).to.equal("FundingNotCanceled");
             });
-            it("Should let buyer claim all IDO tokens he has bought!", async function () {
+            it("Should refund the buyer once the sale is canceled!", async function () {
                 const [buyerInfoPDA, _] = await PublicKey.findProgramAddress(
                     [
                         anchor.utils.bytes.utf8.encode("buyer-info"),
+                        authorizedSaleAccount.publicKey.toBuffer(),
                         provider.wallet.publicKey.toBuffer()
                     ],
                     program.programId
                 );
+                try {
+                    await program.methods
+                        .cancelIdoSale()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                    await program.methods
+                        .claimPayedTokensOnIdoCancel()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).hasClaimedPayTokens).to.equal(true);
+                expect(String((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).spendPayTokens)).to.equal("0");
+                expect(String((await program.account.saleAccount
+                    .fetch(saleAccount)).totalSpendPayTokens)).to.equal("0");
+            });
+        });
+        describe("#soft cap not reached", function () {
+            // Create an account keypair for our program to use.
+            const authorizedSaleAccount = anchor.web3.Keypair.generate();
+            let saleAccount: PublicKey;
+            let buyerInfoPDA: PublicKey;
+            let userPayTokenAccount: PublicKey;
+            let idoTokenMint: PublicKey;
+            before(async function () {
+                try {
+                    saleAccount = await findSaleAccount(authorizedSaleAccount.publicKey);
+                    const now = await chainTime();
+                    const payTokenMint = await createMint(6);
+                    idoTokenMint = await createMint(9);
+                    await program.methods
+                        .initialize(
+                            { numerator: new anchor.BN(2), denominator: new anchor.BN(5) },
+                            new anchor.BN(now + 10),
+                            new anchor.BN(now + 30),
+                            new anchor.BN(now + 7200),
+                            20,
+                            false,
+                            new anchor.BN("1000000000000000"),
+                            false,
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            payTokenMint: payTokenMint,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            user: provider.wallet.publicKey,
+                        })
+                        .signers([authorizedSaleAccount])
+                        .rpc();
+                    await program.methods
+                        .setSoftCapPayTokens(
+                            new anchor.BN(8000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            admin: provider.wallet.publicKey
+                        })
+                        .rpc();
+                    buyerInfoPDA = await createPDA(authorizedSaleAccount.publicKey, provider.wallet.publicKey);
+                    userPayTokenAccount = await createTokenAccount(
+                        payTokenMint,
+                        provider.wallet.publicKey,
+                        new anchor.BN(10000)
+                    );
+                    await program.methods
+                        .setAllocation(
+                            provider.wallet.publicKey,
+                            new anchor.BN("10000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            authority: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                    // Only 4000 of the 8000 pay tokens of the soft cap get raised
+                    await waitUntilAfter(now + 10);
+                    await program.methods
+                        .buy(
+                            new anchor.BN(4000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            allocation: await findAllocation(authorizedSaleAccount.publicKey, provider.wallet.publicKey),
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                    await waitUntilAfter(now + 30);
+                } catch (error) {
+                    console.log(error);
+                }
+            });
+            it(`Should throw error saying "The funding did not reach the soft cap" on claimTokens()!`, async function () {
+                let e: any;
                 try {
                     await program.methods
                         .claimTokens()
                         .accounts({
                             authorizedSaleAccount: authorizedSaleAccount.publicKey,
-                            saleAccount: saleAccount.publicKey,
+                            saleAccount: saleAccount,
                             buyerInfo: buyerInfoPDA,
+                            trancheSchedule: await findTrancheSchedule(authorizedSaleAccount.publicKey),
+                            idoTokenMint: idoTokenMint,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            userIdoTokenAccount: await anchor.utils.token.associatedAddress({
+                                mint: idoTokenMint,
+                                owner: provider.wallet.publicKey
+                            }),
                             user: provider.wallet.publicKey
                         })
                         .rpc();
                 } catch (error) {
                     e = error;
                 }
-                expect(String((await program.account.buyerInfo
-                    .fetch(buyerInfoPDA)).idoTokensClaimed)).to.equal("0");
+                expect(e.error.errorCode.code).to.equal("SoftCapNotReached");
+            });
+            it(`Should throw error saying "The funding did not reach the soft cap" on withdrawPayTokens()!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .withdrawPayTokens(
+                            new anchor.BN(4000)
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            treasuryPayTokenAccount: userPayTokenAccount,
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("SoftCapNotReached");
+                expect((await program.account.saleAccount
+                    .fetch(saleAccount)).totalWithdrawnPayTokens.toNumber()).to.equal(0);
+            });
+            it(`Should throw error saying "The funding did not reach the soft cap" on fundToContract()!`, async function () {
+                let e: any;
+                try {
+                    await program.methods
+                        .fundToContract(
+                            new anchor.BN("10000000")
+                        )
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            idoTokenVault: await findIdoTokenVault(authorizedSaleAccount.publicKey),
+                            adminIdoTokenAccount: await createTokenAccount(
+                                idoTokenMint,
+                                provider.wallet.publicKey,
+                                new anchor.BN("10000000")
+                            ),
+                            admin: provider.wallet.publicKey,
+                        })
+                        .rpc();
+                } catch (error) {
+                    e = error;
+                }
+                expect(e.error.errorCode.code).to.equal("SoftCapNotReached");
+                expect((await program.account.authorizedSaleAccount
+                    .fetch(authorizedSaleAccount.publicKey)).isIdoTokenFundedToContract).to.equal(false);
+            });
+            it("Should refund the buyer once the funding failed to reach the soft cap!", async function () {
+                try {
+                    await program.methods
+                        .claimPayedTokensOnIdoCancel()
+                        .accounts({
+                            authorizedSaleAccount: authorizedSaleAccount.publicKey,
+                            saleAccount: saleAccount,
+                            buyerInfo: buyerInfoPDA,
+                            payTokenVault: await findPayTokenVault(authorizedSaleAccount.publicKey),
+                            userPayTokenAccount: userPayTokenAccount,
+                            user: provider.wallet.publicKey
+                        })
+                        .rpc();
+                } catch (error) {
+                    console.log(error);
+                }
+                expect((await program.account.buyerInfo
+                    .fetch(buyerInfoPDA)).hasClaimedPayTokens).to.equal(true);
+                expect((await tokenProgram.account.token
+                    .fetch(userPayTokenAccount)).amount.toNumber()).to.equal(10000);
+                expect((await tokenProgram.account.token
+                    .fetch(await findPayTokenVault(authorizedSaleAccount.publicKey))).amount.toNumber()).to.equal(0);
             });
         });
     });